@@ -0,0 +1,542 @@
+// Syntax tree for Rive programs. Mirrors the productions in LANGUAGE.ebnf.
+
+use crate::token::NumberSuffix;
+
+/// Identifies a node of a parsed program, so later passes can keep what they find out
+/// about it in side tables instead of in the tree. Definitions, blocks, statements,
+/// expressions, patterns and types each get one. Enums that only wrap one of those, like
+/// `Item`, and plain data like paths and literals don't. Ids are unique within one parse
+/// and count up from 0, so a table can also be a `Vec` indexed by `index()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    /// For nodes that didn't come from the parser, like the ones built by hand in tests.
+    pub const DUMMY: Self = Self(u32::MAX);
+
+    pub(crate) fn new(index: usize) -> Self {
+        Self(u32::try_from(index).expect("too many nodes"))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub items: Vec<TopLevel>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TopLevel {
+    Comment(String),
+    Item(Item),
+    Mod(ModDeclaration),
+    Use(UseStatement),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModDeclaration {
+    pub id: NodeId,
+    pub name: String,
+    pub kind: ModKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ModKind {
+    External,              // `mod foo;`, with the items in another file
+    Inline(Vec<TopLevel>), // `mod foo { ... }`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UseStatement {
+    pub id: NodeId,
+    pub tree: UseTree,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum UseTree {
+    // `a::b`, or `a::b as c`
+    Name { path: Path, alias: Option<String> },
+    // `a::*`
+    Glob(Path),
+    // `a::{b, c::*}`, where each tree continues from `prefix`
+    Group { prefix: Path, trees: Vec<UseTree> },
+}
+
+// `some_module::say_hello`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    pub segments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Item {
+    Protocol(ProtocolDefinition),
+    Struct(StructDefinition),
+    Enum(EnumDefinition),
+    Function(FunctionDefinition),
+    Const(ConstDefinition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolDefinition {
+    pub id: NodeId,
+    pub is_pub: bool,
+    pub name: String,
+    pub generics: Option<GenericParams>,
+    pub parents: Vec<ProtocolRef>,
+    pub members: Vec<ProtocolMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ProtocolMember {
+    Comment(String),
+    Method(FunctionDefinition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDefinition {
+    pub id: NodeId,
+    pub is_pub: bool,
+    pub name: String,
+    pub protocols: Vec<ProtocolRef>,
+    pub members: Vec<StructMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum StructMember {
+    Comment(String),
+    Field(StructField),
+    Method(FunctionDefinition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructField {
+    pub id: NodeId,
+    pub is_pub: bool,
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDefinition {
+    pub id: NodeId,
+    pub is_pub: bool,
+    pub name: String,
+    pub generics: Option<GenericParams>,
+    pub members: Vec<EnumMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EnumMember {
+    Comment(String),
+    Variant(EnumVariant),
+    Method(FunctionDefinition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumVariant {
+    pub id: NodeId,
+    pub name: String,
+    pub payload: Option<VariantPayload>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum VariantPayload {
+    Tuple(Type),               // Some(T);
+    Struct(Vec<VariantField>), // Move { x: int; y: int; };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantField {
+    pub id: NodeId,
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub id: NodeId,
+    pub is_pub: bool,
+    pub name: String,
+    pub generics: Option<GenericParams>,
+    pub params: FunctionParams,
+    pub return_type: Option<Type>,
+    pub body: Option<Block>, // None for protocol method signatures
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum FunctionParams {
+    None,
+    SelfOnly {
+        mutable: bool,
+    },
+    SelfWith {
+        mutable: bool,
+        params: Vec<Parameter>,
+    },
+    List(Vec<Parameter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub id: NodeId,
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstDefinition {
+    pub id: NodeId,
+    pub is_pub: bool,
+    pub name: String,
+    pub ty: Type,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtocolRef {
+    pub id: NodeId,
+    pub name: String,
+    pub generic_args: Vec<Type>, // Empty without `<...>`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericParams {
+    pub params: Vec<GenericParam>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericParam {
+    pub id: NodeId,
+    pub name: String,
+    pub constraints: Vec<ProtocolRef>,
+    pub default: Option<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Type {
+    pub id: NodeId,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TypeKind {
+    Int,
+    Float,
+    Bool,
+    Char,
+    Str,
+    Named(String),
+    Generic { name: String, args: Vec<Type> }, // `Map<str, int>`
+    Tuple(Vec<Type>),                          // (int, str)
+    List(Box<Type>),                           // [int] or List<int>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: NodeId,
+    pub statements: Vec<Statement>,
+    pub final_expr: Option<Box<Expression>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub id: NodeId,
+    pub kind: StatementKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum StatementKind {
+    Comment(String),
+    Let(VariableDefinition),
+    Expression(Expression),
+    Assign { target: Place, value: Expression }, // `target = value;`
+    Break(Option<Expression>),
+    Continue,
+    Return(Option<Expression>),
+}
+
+// What can be assigned to. Keeps the id of the expression it was parsed as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub id: NodeId,
+    pub kind: PlaceKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PlaceKind {
+    Variable(String),
+    Field {
+        expr: Box<Expression>,
+        field: String,
+    },
+    TupleIndex {
+        expr: Box<Expression>,
+        index: usize,
+    },
+    Index {
+        expr: Box<Expression>,
+        index: Box<Expression>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDefinition {
+    pub id: NodeId,
+    pub mutable: bool,
+    pub name: String,
+    pub ty: Option<Type>,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub id: NodeId,
+    pub kind: ExpressionKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ExpressionKind {
+    Literal(Literal),
+    Identifier(String),
+    SelfRef, // `self` inside a method body
+    Binary {
+        left: Box<Expression>,
+        op: BinaryOperator,
+        right: Box<Expression>,
+    },
+    Unary {
+        op: UnaryOperator,
+        expr: Box<Expression>,
+    },
+    If {
+        condition: Box<Expression>,
+        then_block: Block,
+        else_clause: Option<ElseClause>,
+    },
+    Unless {
+        condition: Box<Expression>,
+        then_block: Block,
+        else_block: Option<Block>,
+    },
+    Block(Block),
+    Call {
+        callee: Box<Expression>,
+        args: Vec<Expression>,
+    },
+    Loop(Block),
+    For {
+        var: String,
+        iter: Box<Expression>,
+        body: Block,
+    },
+    While {
+        condition: Box<Expression>,
+        body: Block,
+    },
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+        inclusive: bool,
+    },
+    Match {
+        scrutinee: Box<Expression>,
+        arms: Vec<MatchArm>,
+    },
+    StructLiteral {
+        name: String,
+        fields: Vec<FieldInit>,
+        base: Option<Box<Expression>>, // `..base`, which the fields not listed come from
+    },
+    EnumLiteral {
+        enum_name: String,
+        variant: String,
+        payload: Option<EnumPayload>,
+    },
+    Tuple(Vec<Expression>), // (a, b)
+    List(Vec<Expression>),  // [a, b]
+    FieldAccess {
+        expr: Box<Expression>,
+        field: String,
+    },
+    MethodCall {
+        receiver: Box<Expression>,
+        method: String,
+        args: Vec<Expression>,
+    },
+    Closure {
+        params: Vec<ClosureParam>,
+        return_type: Option<Type>,
+        body: Box<Expression>,
+    },
+    Try(Box<Expression>), // `expr?`, returns early on the error variant
+    // `value ?? default`, the payload of an Option-like value or `default`
+    Coalesce {
+        value: Box<Expression>,
+        default: Box<Expression>,
+    },
+    // `expr?.field`, short-circuits to the empty variant
+    OptionalFieldAccess {
+        expr: Box<Expression>,
+        field: String,
+    },
+    // `pair.0`
+    TupleIndex {
+        expr: Box<Expression>,
+        index: usize,
+    },
+    // `expr[index]`
+    Index {
+        expr: Box<Expression>,
+        index: Box<Expression>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ElseClause {
+    Block(Block),
+    If(Box<Expression>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EnumPayload {
+    Tuple(Box<Expression>),
+    Struct(Vec<FieldInit>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldInit {
+    pub id: NodeId,
+    pub name: String,
+    pub value: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub id: NodeId,
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Expression,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClosureParam {
+    pub id: NodeId,
+    pub name: String,
+    pub ty: Option<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Literal {
+    Integer(String, Option<NumberSuffix>), // Source text, so formatting survives
+    Float(String, Option<NumberSuffix>),
+    Bool(bool),
+    Char(char),
+    String(Vec<StringContent>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum StringContent {
+    Text(String),
+    Interpolated(Box<Expression>), // #{expr}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BinaryOperator {
+    Add,    // '+'
+    Sub,    // '-'
+    Mul,    // '*'
+    Div,    // '/'
+    Mod,    // '%'
+    Pow,    // '**'
+    Eq,     // '=='
+    NotEq,  // '!='
+    Lt,     // '<'
+    Gt,     // '>'
+    Le,     // '<='
+    Ge,     // '>='
+    Or,     // '||'
+    And,    // '&&'
+    BitAnd, // '&'
+    BitOr,  // '|'
+    BitXor, // '^'
+    Shl,    // '<<'
+    Shr,    // '>>'
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnaryOperator {
+    Neg,    // '-'
+    Not,    // '!'
+    BitNot, // '~'
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub id: NodeId,
+    pub kind: PatternKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum PatternKind {
+    Literal(Literal), // A negated number keeps its `-` in the literal text
+    Identifier(String),
+    Wildcard, // '_'
+    Range {
+        start: Literal,
+        end: Literal,
+        inclusive: bool,
+    },
+    // `Point { x, y: 0, .. }`. A single name followed by `{` is parsed as this, even if it
+    // turns out to be a variant.
+    Struct {
+        name: String,
+        fields: Vec<PatternField>,
+        rest: bool, // Ends in `..`
+    },
+    Enum {
+        path: Path,
+        payload: Option<EnumPatternPayload>,
+    },
+    Tuple(Vec<Pattern>), // (a, b)
+    List(Vec<Pattern>),  // [a, b], a list of exactly that many elements unless one is `Rest`
+    Or(Vec<Pattern>),    // a | b, with at least two alternatives
+    Rest,                // `..` in a list pattern, matching any number of elements
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EnumPatternPayload {
+    Tuple(Box<Pattern>),
+    Struct(Vec<PatternField>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternField {
+    pub id: NodeId,
+    pub name: String,
+    pub pattern: Pattern,
+}
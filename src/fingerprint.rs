@@ -0,0 +1,149 @@
+use std::fmt;
+
+use crate::{
+    lexer::Lexer,
+    token::{StringPart, Token, WithSpan},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct FingerprintOptions {
+    pub include_comments: bool,
+}
+
+/// Stable content hash of a token stream. Whitespace between tokens never affects it,
+/// and the value is the same across builds and platforms (64-bit FNV-1a over the token
+/// texts), so it can be stored in caches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    pub fn of_source(source: &str, options: &FingerprintOptions) -> Self {
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        Self::of_tokens(source, &tokens, options)
+    }
+
+    /// `tokens` must have been lexed from `source`.
+    pub fn of_tokens(
+        source: &str,
+        tokens: &[WithSpan<Token>],
+        options: &FingerprintOptions,
+    ) -> Self {
+        let mut hasher = Fnv1a::new();
+        hash_tokens(&mut hasher, source, tokens, options);
+        Self(hasher.0)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+fn hash_tokens(
+    hasher: &mut Fnv1a,
+    source: &str,
+    tokens: &[WithSpan<Token>],
+    options: &FingerprintOptions,
+) {
+    for token in tokens {
+        match &token.value {
+            Token::Eof => {}
+            Token::Comment(_) if !options.include_comments => {}
+            // The text is hashed from the source like any other token, and each `#{...}`
+            // token by token so whitespace inside it doesn't count either. An interpolation's
+            // tokens end with an `Eof` at its closing `}`.
+            Token::InterpolatedString(parts) => {
+                let mut start = token.span.start;
+                for part in parts {
+                    if let StringPart::Interpolation(tokens) = part
+                        && let (Some(first), Some(close)) = (tokens.first(), tokens.last())
+                    {
+                        hasher.write_chunk(source[start..first.span.start].trim_end().as_bytes());
+                        hash_tokens(hasher, source, tokens, options);
+                        start = close.span.start;
+                    }
+                }
+                hasher.write_chunk(&source.as_bytes()[start..token.span.end]);
+            }
+            _ => hasher.write_chunk(&source.as_bytes()[token.span.start..token.span.end]),
+        }
+    }
+}
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    // Length-prefixed, so adjacent chunks can't run together ("ab" vs "a" "b")
+    fn write_chunk(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(source: &str) -> Fingerprint {
+        Fingerprint::of_source(source, &FingerprintOptions::default())
+    }
+
+    #[test]
+    fn test_stable_value() {
+        assert_eq!(Fnv1a::new().0, 0xcbf2_9ce4_8422_2325);
+        let mut hasher = Fnv1a::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.0, 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fingerprint("").to_string(), "cbf29ce484222325");
+    }
+
+    #[test]
+    fn test_ignores_whitespace() {
+        assert_eq!(
+            fingerprint("let a = \"x #{ b + 1 }\";"),
+            fingerprint("let a=\n\t\"x #{b+1}\" ;")
+        );
+        assert_ne!(fingerprint("a b"), fingerprint("ab"));
+        assert_ne!(fingerprint("\"a b\""), fingerprint("\"ab\""));
+        assert_ne!(fingerprint("\"a #{b}\""), fingerprint("\"a#{b}\""));
+        assert_ne!(fingerprint("\"#{a} b\""), fingerprint("\"#{a}b\""));
+    }
+
+    #[test]
+    fn test_escapes() {
+        // Strings are hashed as written, so an escape differs from the character it stands for
+        assert_ne!(fingerprint("\"a\\tb\""), fingerprint("\"a\tb\""));
+        assert_ne!(fingerprint("\"a\\tb #{c}\""), fingerprint("\"a\tb #{c}\""));
+        assert_ne!(fingerprint("\"\\#{c}\""), fingerprint("\"#{c}\""));
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "let a = 1; # one";
+        assert_eq!(fingerprint(source), fingerprint("let a = 1; #* uno *#"));
+        assert_eq!(fingerprint(source), fingerprint("let a = 1;"));
+
+        let options = FingerprintOptions {
+            include_comments: true,
+        };
+        assert_ne!(
+            Fingerprint::of_source(source, &options),
+            Fingerprint::of_source("let a = 1;", &options)
+        );
+    }
+}
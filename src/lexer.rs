@@ -1,62 +1,163 @@
-use std::{iter::Peekable, str::Chars};
+mod stream;
 
-use crate::token::{Span, Token, WithSpan};
+use std::fmt;
 
+pub use stream::StreamLexer;
+
+use crate::{
+    source::TextEdit,
+    token::{NumberSuffix, Span, StringPart, Token, TokenStream, WithSpan},
+};
+
+/// How deeply `#{...}` interpolations may nest inside each other. Each level recurses,
+/// so this keeps hostile input from overflowing the stack.
+pub const MAX_INTERPOLATION_DEPTH: usize = 32;
+
+// How many bytes past the end of a token lexing it may look at. A number looks at two, so
+// `1.a` is an integer followed by `.a` while `1.5` is a float.
+const LOOKAHEAD: usize = 2;
+
+/// A malformed piece of source. Lexing carries on past it: `recovery` is the token
+/// emitted in its place, or `None` if the text was skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+    pub recovery: Option<Token>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LexErrorKind {
+    UnknownChar(char),
+    UnterminatedString,
+    UnterminatedChar,
+    UnterminatedComment,
+    InvalidCharLiteral, // More than one char in char literal
+    InvalidNumber,      // Misplaced `_`, bad digits or suffix, e.g. `1__0`, `0x`, `0b12`, `1px`
+    IntegerOverflow,    // Integer literal that doesn't fit in a u64
+    NestingTooDeep,     // Interpolations nested past MAX_INTERPOLATION_DEPTH
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownChar(ch) => write!(f, "unknown character `{ch}`"),
+            Self::UnterminatedString => write!(f, "unterminated string"),
+            Self::UnterminatedChar => write!(f, "unterminated character literal"),
+            Self::UnterminatedComment => write!(f, "unterminated comment"),
+            Self::InvalidCharLiteral => {
+                write!(f, "character literal may only contain one character")
+            }
+            Self::InvalidNumber => write!(f, "invalid number"),
+            Self::IntegerOverflow => write!(f, "integer literal is too large"),
+            Self::NestingTooDeep => write!(f, "too deeply nested interpolation"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+// Scans the source as bytes. Every token starts with an ASCII byte, so multi-byte
+// characters only need decoding inside strings, chars, comments, and whitespace.
 pub struct Lexer<'a> {
-    chars: Peekable<Chars<'a>>,
+    bytes: &'a [u8],
     pos: usize,
     source: &'a str,
+    emitted_eof: bool,
+    nesting: usize,              // Interpolations enclosing this lexer
+    error: Option<LexErrorKind>, // Set while lexing a malformed token
+    errors: Vec<LexError>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
+        Self::starting_at(source, 0)
+    }
+
+    // Lexes `source` from byte offset `pos`, keeping spans relative to the whole source
+    fn starting_at(source: &'a str, pos: usize) -> Self {
         Self {
-            chars: source.chars().peekable(),
-            pos: 0,
+            bytes: source.as_bytes(),
+            pos,
             source,
+            emitted_eof: false,
+            nesting: 0,
+            error: None,
+            errors: vec![],
         }
     }
 
-    fn next(&mut self) -> Option<char> {
-        let op = self.chars.next();
-        if let Some(ch) = op {
-            self.pos += ch.len_utf8();
-        }
-        op
+    /// Lexes the rest of the source, ending with `Token::Eof`, along with every error
+    /// found on the way.
+    pub fn lex_all(mut self) -> (TokenStream, Vec<LexError>) {
+        let tokens = self.by_ref().collect();
+        (tokens, self.errors)
+    }
+
+    /// Errors found in the tokens produced so far.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    // Reports the token being lexed as malformed, with `recovery` standing in for it
+    fn fail(&mut self, kind: LexErrorKind, recovery: Token) -> Option<Token> {
+        self.error = Some(kind);
+        Some(recovery)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    // Only call when the current byte is known to be ASCII
+    fn bump(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek_char()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
     }
 
     fn consume_if<F>(&mut self, f: F) -> bool
     where
-        F: Fn(char) -> bool,
+        F: Fn(u8) -> bool,
     {
-        if let Some(ch) = self.chars.peek() {
-            if f(*ch) {
-                self.next();
+        match self.peek() {
+            Some(byte) if f(byte) => {
+                self.pos += 1;
                 true
-            } else {
-                false
             }
-        } else {
-            false
+            _ => false,
         }
     }
 
-    fn consume_while<F>(&mut self, x: F) -> String
+    // `f` must only accept ASCII bytes or reject a whole character at once, so the
+    // returned slice always ends on a char boundary.
+    fn consume_while<F>(&mut self, f: F) -> &'a str
     where
-        F: Fn(char) -> bool,
+        F: Fn(u8) -> bool,
     {
         let start = self.pos;
-        while let Some(&ch) = self.chars.peek() {
-            if x(ch) {
-                self.next().unwrap();
-            } else {
-                break;
-            }
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
         }
-        self.source[start..self.pos].to_string()
+        &self.source[start..self.pos]
     }
 
-    fn either(&mut self, to_match: char, matched: Token, unmatched: Token) -> Option<Token> {
+    fn either(&mut self, to_match: u8, matched: Token, unmatched: Token) -> Option<Token> {
         if self.consume_if(|x| x == to_match) {
             return Some(matched);
         }
@@ -64,101 +165,330 @@ impl<'a> Lexer<'a> {
     }
 
     fn skip_whitespace(&mut self) {
-        self.consume_while(|x| x.is_whitespace());
+        loop {
+            match self.peek() {
+                Some(byte) if byte.is_ascii_whitespace() => self.pos += 1,
+                Some(byte) if !byte.is_ascii() => match self.peek_char() {
+                    Some(ch) if ch.is_whitespace() => self.pos += ch.len_utf8(),
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
     }
 
-    fn lex_number(&mut self, ch: char, is_negative: bool) -> Option<Token> {
-        let start = self.pos - ch.len_utf8();
-        self.consume_while(|x| x.is_digit(10));
-        let is_float = self.consume_if(|x| x == '.');
+    // `start` points at the first digit, which has already been consumed
+    fn lex_number(&mut self, start: usize) -> Option<Token> {
+        if self.bytes[start] == b'0' {
+            let radix = match self.peek() {
+                Some(b'x') => Some(16),
+                Some(b'o') => Some(8),
+                Some(b'b') => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                self.bump(); // Consume the radix prefix
+                return self.lex_radix_integer(start, radix);
+            }
+        }
+
+        self.consume_while(|x| x.is_ascii_digit() || x == b'_');
+        // `1..2` is a range, not the float `1.` followed by `.2`, and `1.abs()` and
+        // `pair.0.name` access a member of the integer
+        let is_float = self.peek() == Some(b'.')
+            && self
+                .bytes
+                .get(self.pos + 1)
+                .is_none_or(|&x| x != b'.' && !x.is_ascii_alphabetic());
         if is_float {
-            self.consume_while(|x| x.is_digit(10));
-            self.source
-                .get(start..self.pos)?
-                .parse::<f64>()
-                .ok()
-                .map(|x| Token::Float(if is_negative { -x } else { x }))
+            self.bump();
+            self.consume_while(|x| x.is_ascii_digit() || x == b'_');
+        }
+        let digits_end = self.pos;
+        let suffix = self.lex_suffix();
+
+        let digits = &self.source[start..digits_end];
+        let valid = digits.split('.').all(Self::valid_separators);
+        let suffix = match suffix {
+            Some(suffix) if valid && !(is_float && suffix.is_some_and(|x| !x.is_float())) => suffix,
+            _ => return self.invalid_number(start, is_float),
+        };
+
+        let text = self.source[start..digits_end].to_string();
+        let digits = text.replace('_', "");
+        if is_float {
+            match digits.parse::<f64>() {
+                Ok(value) => Some(Token::Float {
+                    text,
+                    value,
+                    suffix,
+                }),
+                Err(_) => self.invalid_number(start, true),
+            }
         } else {
-            self.source
-                .get(start..self.pos)?
-                .parse::<i64>()
-                .ok()
-                .map(|x| Token::Int(if is_negative { -x } else { x }))
+            match digits.parse::<u64>() {
+                Ok(value) => Some(Token::Int {
+                    text,
+                    value,
+                    suffix,
+                }),
+                Err(_) => self.fail(
+                    LexErrorKind::IntegerOverflow,
+                    Token::Int {
+                        text,
+                        value: 0,
+                        suffix,
+                    },
+                ),
+            }
         }
     }
 
+    // `start` points at the leading '0' of a `0x`, `0o` or `0b` literal
+    fn lex_radix_integer(&mut self, start: usize, radix: u32) -> Option<Token> {
+        // Decimal digits out of range for the radix are taken in too, so `0b12` is one
+        // invalid number rather than `0b1` followed by `2`
+        let is_digit = |x: u8| match radix {
+            16 => x.is_ascii_hexdigit(),
+            _ => x.is_ascii_digit(),
+        };
+        let digits = self.consume_while(|x| is_digit(x) || x == b'_');
+        let digits_end = self.pos;
+        let valid = !digits.is_empty()
+            && digits.chars().all(|x| x.is_digit(radix) || x == '_')
+            && Self::valid_separators(digits);
+        let suffix = match self.lex_suffix() {
+            Some(suffix) if valid => suffix,
+            _ => return self.invalid_number(start, false),
+        };
+
+        let text = self.source[start..digits_end].to_string();
+        match u64::from_str_radix(&digits.replace('_', ""), radix) {
+            Ok(value) => Some(Token::Int {
+                text,
+                value,
+                suffix,
+            }),
+            Err(_) => self.fail(
+                LexErrorKind::IntegerOverflow,
+                Token::Int {
+                    text,
+                    value: 0,
+                    suffix,
+                },
+            ),
+        }
+    }
+
+    // Recovers from a malformed literal as a zero that keeps the whole text
+    fn invalid_number(&mut self, start: usize, is_float: bool) -> Option<Token> {
+        let text = self.source[start..self.pos].to_string();
+        let recovery = if is_float {
+            Token::Float {
+                text,
+                value: 0.0,
+                suffix: None,
+            }
+        } else {
+            Token::Int {
+                text,
+                value: 0,
+                suffix: None,
+            }
+        };
+        self.fail(LexErrorKind::InvalidNumber, recovery)
+    }
+
+    // Lexes a type suffix directly following a number. `None` means the suffix is unknown.
+    fn lex_suffix(&mut self) -> Option<Option<NumberSuffix>> {
+        if !self.peek().is_some_and(|x| x.is_ascii_alphabetic()) {
+            return Some(None);
+        }
+        let suffix = self.consume_while(|x| x.is_ascii_alphanumeric() || x == b'_');
+        NumberSuffix::parse(suffix).map(Some)
+    }
+
+    // Underscores are only allowed between two digits
+    fn valid_separators(digits: &str) -> bool {
+        !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+    }
+
     fn lex_string(&mut self) -> Option<Token> {
+        let mut parts = vec![];
         let mut value = String::new();
-        let mut escaped = false;
-
-        while let Some(ch) = self.next() {
-            if escaped {
-                let escaped_char = match ch {
-                    'n' => '\n',
-                    'r' => '\r',
-                    't' => '\t',
-                    '\\' => '\\',
-                    '"' => '"',
-                    _ => {
-                        value.push('\\'); // Keep the backslash as a normal character
-                        ch // Add the unknown escape character as is
+
+        loop {
+            // Copy the run up to the next special byte in one go
+            let run = self.consume_while(|x| !matches!(x, b'"' | b'\\' | b'#'));
+            value.push_str(run);
+
+            match self.bump() {
+                Some(b'"') => return Some(Self::string_token(parts, value)),
+                Some(b'\\') => {
+                    let Some(ch) = self.next_char() else {
+                        break;
+                    };
+                    let escaped_char = match ch {
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        '\\' => '\\',
+                        '"' => '"',
+                        '#' => '#',
+                        _ => {
+                            value.push('\\'); // Keep the backslash as a normal character
+                            ch // Add the unknown escape character as is
+                        }
+                    };
+                    value.push(escaped_char);
+                }
+                Some(b'#') if self.consume_if(|x| x == b'{') => {
+                    if self.nesting >= MAX_INTERPOLATION_DEPTH {
+                        // There's no telling where the string ends without lexing deeper
+                        self.pos = self.source.len();
+                        let recovery = Self::string_token(parts, value);
+                        return self.fail(LexErrorKind::NestingTooDeep, recovery);
+                    }
+                    if !value.is_empty() {
+                        parts.push(StringPart::Text(std::mem::take(&mut value)));
+                    }
+                    let (tokens, closed) = self.lex_interpolation();
+                    parts.push(StringPart::Interpolation(tokens));
+                    if !closed {
+                        return Some(Self::string_token(parts, value));
                     }
-                };
-                value.push(escaped_char);
-                escaped = false;
-            } else if ch == '\\' {
-                escaped = true;
-            } else if ch == '"' {
-                return Some(Token::String(value));
-            } else {
-                value.push(ch);
+                }
+                Some(_) => value.push('#'),
+                None => break,
+            }
+        }
+
+        let recovery = Self::string_token(parts, value);
+        self.fail(LexErrorKind::UnterminatedString, recovery)
+    }
+
+    fn string_token(mut parts: Vec<StringPart>, value: String) -> Token {
+        if parts.is_empty() {
+            return Token::String(value);
+        }
+        if !value.is_empty() {
+            parts.push(StringPart::Text(value));
+        }
+        Token::InterpolatedString(parts)
+    }
+
+    // Re-lexes the contents of `#{...}` up to the matching `}`, which is where the returned
+    // tokens put their `Eof`. If the source ends first, the whole string is unterminated
+    // and only the tokens so far are returned, followed by the inner lexer's `Eof`.
+    fn lex_interpolation(&mut self) -> (Vec<WithSpan<Token>>, bool) {
+        let mut inner = Lexer::starting_at(self.source, self.pos);
+        inner.nesting = self.nesting + 1;
+        let mut tokens = vec![];
+        let mut depth = 0;
+        let mut closed = false;
+
+        for token in inner.by_ref() {
+            match token.value {
+                Token::Eof => {
+                    tokens.push(token);
+                    break;
+                }
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => {
+                    // Catch up with the inner lexer
+                    self.pos = token.span.end;
+                    closed = true;
+                    let end = token.span.start;
+                    tokens.push(WithSpan {
+                        value: Token::Eof,
+                        span: Span { start: end, end },
+                    });
+                    break;
+                }
+                Token::RBrace => depth -= 1,
+                _ => {}
             }
+            tokens.push(token);
         }
 
-        Some(Token::UnterminatedString)
+        // Nesting too deep already explains why every enclosing string runs to the end
+        let too_deep = inner
+            .errors
+            .iter()
+            .any(|e| e.kind == LexErrorKind::NestingTooDeep);
+        self.errors.append(&mut inner.errors);
+        if !closed {
+            self.pos = self.source.len();
+            if !too_deep {
+                self.error = Some(LexErrorKind::UnterminatedString);
+            }
+        }
+        (tokens, closed)
     }
 
     fn lex_char(&mut self) -> Option<Token> {
-        let Some(mut ch) = self.next() else {
-            return Some(Token::UnterminatedChar);
+        let Some(mut ch) = self.next_char() else {
+            return self.fail(
+                LexErrorKind::UnterminatedChar,
+                Token::Char(char::REPLACEMENT_CHARACTER),
+            );
         };
 
         if ch == '\\' {
-            ch = match self.next() {
+            ch = match self.next_char() {
                 Some('n') => '\n',
                 Some('r') => '\r',
                 Some('t') => '\t',
                 Some('\\') => '\\',
                 Some('\'') => '\'',
                 Some(other) => other, // Unknown escapes are treated literally
-                None => return Some(Token::UnterminatedChar), // Unterminated escape
+                None => {
+                    return self.fail(
+                        LexErrorKind::UnterminatedChar,
+                        Token::Char(char::REPLACEMENT_CHARACTER),
+                    );
+                }
             };
         }
 
-        // If another character is found before the closing single quote, it's invalid
-        if let Some(next) = self.next() {
-            if next != '\'' {
-                return Some(Token::InvalidCharLiteral);
+        match self.next_char() {
+            Some('\'') => Some(Token::Char(ch)),
+            Some('\n') | None => self.fail(LexErrorKind::UnterminatedChar, Token::Char(ch)),
+            Some(_) => {
+                // Skip the rest of an overlong literal up to where it closes, or else the
+                // whole line. The token covers everything looked at, keeping the lookahead
+                // past its end within `LOOKAHEAD`.
+                let rest = &self.source[self.pos..];
+                match rest.find(['\'', '\n']) {
+                    Some(len) if rest.as_bytes()[len] == b'\'' => {
+                        self.pos += len + 1;
+                        self.fail(LexErrorKind::InvalidCharLiteral, Token::Char(ch))
+                    }
+                    len => {
+                        self.pos += len.unwrap_or(rest.len());
+                        self.fail(LexErrorKind::UnterminatedChar, Token::Char(ch))
+                    }
+                }
             }
-            Some(Token::Char(ch))
-        } else {
-            Some(Token::UnterminatedChar)
         }
     }
 
-    fn lex_identifier(&mut self, ch: char) -> Option<Token> {
-        let start = self.pos - ch.len_utf8();
-        self.consume_while(|x| x.is_ascii_alphanumeric() || x == '_');
-
-        let ident = self.source.get(start..self.pos)?;
+    // `start` points at the first character, which has already been consumed
+    fn lex_identifier(&mut self, start: usize) -> Option<Token> {
+        self.consume_while(|x| x.is_ascii_alphanumeric() || x == b'_');
 
+        let ident = &self.source[start..self.pos];
         Some(match ident {
+            "as" => Token::As,
             "break" => Token::Break,
             "const" => Token::Const,
             "continue" => Token::Continue,
+            "else" => Token::Else,
             "enum" => Token::Enum,
             "fn" => Token::Fn,
             "for" => Token::For,
+            "if" => Token::If,
+            "in" => Token::In,
             "let" => Token::Let,
             "loop" => Token::Loop,
             "match" => Token::Match,
@@ -166,7 +496,10 @@ impl<'a> Lexer<'a> {
             "mut" => Token::Mut,
             "proto" => Token::Proto,
             "pub" => Token::Pub,
+            "return" => Token::Return,
+            "self" => Token::SelfKw,
             "struct" => Token::Struct,
+            "unless" => Token::Unless,
             "use" => Token::Use,
             "while" => Token::While,
             "false" => Token::Bool(false),
@@ -176,92 +509,88 @@ impl<'a> Lexer<'a> {
     }
 
     fn lex_comment(&mut self) -> Option<Token> {
-        if self.consume_if(|x| x == '*') {
+        if self.consume_if(|x| x == b'*') {
             // Multi-line comment
             let start = self.pos;
-            let mut end = None; // Track the last valid comment position
-
-            while let Some(&ch) = self.chars.peek() {
-                let pos_before = self.pos;
-                self.next(); // Consume current character
-
-                if ch == '*' {
-                    if let Some(&'#') = self.chars.peek() {
-                        self.next(); // Consume '#'
-                        end = Some(pos_before); // Store position *before* `*#`
-                        break;
-                    }
+            match self.source[start..].find("*#") {
+                Some(len) => {
+                    self.pos = start + len + 2; // Skip past `*#`
+                    Some(Token::Comment(self.source[start..start + len].to_string()))
+                }
+                None => {
+                    self.pos = self.source.len();
+                    let text = self.source[start..].to_string();
+                    self.fail(LexErrorKind::UnterminatedComment, Token::Comment(text))
                 }
-            }
-
-            if let Some(end_pos) = end {
-                Some(Token::Comment(self.source[start..end_pos].to_string()))
-            } else {
-                // Unterminated comment
-                Some(Token::UnterminatedComment(
-                    self.source[start..self.pos].to_string(),
-                ))
             }
         } else {
             // Single-line comment
-            let start = self.pos;
-            self.consume_while(|x| x != '\n');
-            Some(Token::Comment(self.source[start..self.pos].to_string()))
+            let comment = self.consume_while(|x| x != b'\n');
+            Some(Token::Comment(comment.to_string()))
         }
     }
 
+    // Lexes the token starting at the current byte, which must exist. `None` means the
+    // text was skipped, which is always reported as an error.
     fn lex(&mut self) -> Option<Token> {
-        let ch = self.next()?;
-        match ch {
-            '(' => Some(Token::LParen),
-            ')' => Some(Token::RParen),
-            '*' => Some(Token::Star),
-            '+' => Some(Token::Plus),
-            ',' => Some(Token::Comma),
-            '/' => Some(Token::Slash),
-            ';' => Some(Token::Semicolon),
-            '[' => Some(Token::LBracket),
-            ']' => Some(Token::RBracket),
-            '{' => Some(Token::LBrace),
-            '}' => Some(Token::RBrace),
-            '^' => Some(Token::Caret),
-            '~' => Some(Token::Tilde),
-            '%' => Some(Token::Percent),
-            '&' => self.either('&', Token::And, Token::Amp),
-            '|' => self.either('|', Token::Or, Token::Pipe),
-            ':' => self.either(':', Token::DoubleColon, Token::Colon),
-            '!' => self.either('=', Token::NotEq, Token::Bang),
-            '=' => self.either('=', Token::EqEq, Token::Eq),
-            '-' => {
-                if self.consume_if(|x| x.is_digit(10)) {
-                    self.lex_number(ch, true)
-                } else if self.consume_if(|x| x == '>') {
-                    Some(Token::Arrow)
+        let start = self.pos;
+        let byte = self.bytes[start];
+        if !byte.is_ascii() {
+            self.error = self.next_char().map(LexErrorKind::UnknownChar);
+            return None;
+        }
+        self.bump();
+        match byte {
+            b'(' => Some(Token::LParen),
+            b')' => Some(Token::RParen),
+            b'*' => self.either(b'*', Token::StarStar, Token::Star),
+            b'+' => Some(Token::Plus),
+            b',' => Some(Token::Comma),
+            b'/' => Some(Token::Slash),
+            b';' => Some(Token::Semicolon),
+            b'[' => Some(Token::LBracket),
+            b']' => Some(Token::RBracket),
+            b'{' => Some(Token::LBrace),
+            b'}' => Some(Token::RBrace),
+            b'^' => Some(Token::Caret),
+            b'~' => Some(Token::Tilde),
+            b'%' => Some(Token::Percent),
+            b'?' => {
+                if self.consume_if(|x| x == b'?') {
+                    Some(Token::DoubleQuestion)
+                } else if self.consume_if(|x| x == b'.') {
+                    Some(Token::QuestionDot)
                 } else {
-                    Some(Token::Minus)
+                    Some(Token::Question)
                 }
             }
-            '<' => {
-                if self.consume_if(|x| x == '=') {
+            b'&' => self.either(b'&', Token::And, Token::Amp),
+            b'|' => self.either(b'|', Token::Or, Token::Pipe),
+            b':' => self.either(b':', Token::DoubleColon, Token::Colon),
+            b'!' => self.either(b'=', Token::NotEq, Token::Bang),
+            b'=' => self.either(b'=', Token::EqEq, Token::Eq),
+            b'-' => self.either(b'>', Token::Arrow, Token::Minus),
+            b'<' => {
+                if self.consume_if(|x| x == b'=') {
                     Some(Token::Le)
-                } else if self.consume_if(|x| x == '<') {
+                } else if self.consume_if(|x| x == b'<') {
                     Some(Token::LShift)
                 } else {
                     Some(Token::Lt)
                 }
             }
-            '>' => {
-                if self.consume_if(|x| x == '=') {
+            b'>' => {
+                if self.consume_if(|x| x == b'=') {
                     Some(Token::Ge)
-                } else if self.consume_if(|x| x == '>') {
+                } else if self.consume_if(|x| x == b'>') {
                     Some(Token::RShift)
                 } else {
                     Some(Token::Gt)
                 }
             }
-            '.' => {
-                if self.consume_if(|x| x == '.') {
-                    if self.consume_if(|x| x == '=') {
+            b'.' => {
+                if self.consume_if(|x| x == b'.') {
+                    if self.consume_if(|x| x == b'=') {
                         Some(Token::RangeInclusive)
                     } else {
                         Some(Token::RangeExclusive)
@@ -270,28 +599,118 @@ impl<'a> Lexer<'a> {
                     Some(Token::Dot)
                 }
             }
-            '#' => self.lex_comment(),
-            '"' => self.lex_string(),
-            '\'' => self.lex_char(),
-            '0'..='9' => self.lex_number(ch, false),
-            'a'..='z' | 'A'..='Z' | '_' => self.lex_identifier(ch),
-            _ => Some(Token::Unknown(ch)),
+            b'#' => self.lex_comment(),
+            b'"' => self.lex_string(),
+            b'\'' => self.lex_char(),
+            b'0'..=b'9' => self.lex_number(start),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.lex_identifier(start),
+            _ => {
+                self.error = Some(LexErrorKind::UnknownChar(byte as char));
+                None
+            }
         }
     }
 }
 
+impl Lexer<'_> {
+    /// Re-tokenizes `source` (the text after `edit`) given the tokens of the text before
+    /// it. Tokens ahead of the edit are reused, lexing restarts at the last token boundary
+    /// before it, and once a new token lines up with an old one past the edit, the rest of
+    /// the old tokens are shifted into place instead of being lexed again. Errors are not
+    /// tracked across edits; `lex_all` reports them for the whole source.
+    pub fn relex(source: &str, old_tokens: &[WithSpan<Token>], edit: &TextEdit) -> TokenStream {
+        // A token ending close enough to the edit to have looked at what it changed may come
+        // out differently, so it is lexed again
+        let first = old_tokens.partition_point(|t| t.span.end + LOOKAHEAD <= edit.span.start);
+        let restart = first
+            .checked_sub(1)
+            .map_or(0, |prev| old_tokens[prev].span.end);
+
+        let mut tokens = old_tokens[..first].to_vec();
+        let delta = edit.delta();
+        // Old tokens starting after the edit are candidates for resynchronizing
+        let mut old = first + old_tokens[first..].partition_point(|t| t.span.start < edit.span.end);
+
+        for token in Lexer::starting_at(source, restart) {
+            while old_tokens
+                .get(old)
+                .is_some_and(|t| t.span.start.saturating_add_signed(delta) < token.span.start)
+            {
+                old += 1;
+            }
+            if old_tokens
+                .get(old)
+                .is_some_and(|t| t.span.start.saturating_add_signed(delta) == token.span.start)
+            {
+                // Lexing only depends on the position, so everything from here on matches
+                tokens.extend(old_tokens[old..].iter().map(|t| shift(t, delta)));
+                return tokens;
+            }
+            tokens.push(token);
+        }
+        tokens
+    }
+}
+
+fn shift(token: &WithSpan<Token>, delta: isize) -> WithSpan<Token> {
+    let value = match &token.value {
+        Token::InterpolatedString(parts) => Token::InterpolatedString(
+            parts
+                .iter()
+                .map(|part| match part {
+                    StringPart::Interpolation(tokens) => {
+                        StringPart::Interpolation(tokens.iter().map(|t| shift(t, delta)).collect())
+                    }
+                    text => text.clone(),
+                })
+                .collect(),
+        ),
+        value => value.clone(),
+    };
+    WithSpan {
+        value,
+        span: Span {
+            start: token.span.start.saturating_add_signed(delta),
+            end: token.span.end.saturating_add_signed(delta),
+        },
+    }
+}
+
 impl<'a> Iterator for Lexer<'a> {
     type Item = WithSpan<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.skip_whitespace();
-        let start = self.pos;
-        let value = self.lex()?;
-        let span = Span {
-            start,
-            end: self.pos,
-        };
-        Some(WithSpan { value, span })
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            if start >= self.bytes.len() {
+                if self.emitted_eof {
+                    return None;
+                }
+                self.emitted_eof = true;
+                let span = Span { start, end: start };
+                return Some(WithSpan {
+                    value: Token::Eof,
+                    span,
+                });
+            }
+
+            let value = self.lex();
+            let span = Span {
+                start,
+                end: self.pos,
+            };
+            if let Some(kind) = self.error.take() {
+                self.errors.push(LexError {
+                    kind,
+                    span: span.clone(),
+                    recovery: value.clone(),
+                });
+            }
+            if let Some(value) = value {
+                return Some(WithSpan { value, span });
+            }
+        }
     }
 }
 
@@ -299,15 +718,121 @@ impl<'a> Iterator for Lexer<'a> {
 mod tests {
     use super::*;
 
-    fn lex<'a>(source: &'a str) -> Vec<Token> {
+    fn int(text: &str, value: u64, suffix: Option<NumberSuffix>) -> Token {
+        Token::Int {
+            text: text.into(),
+            value,
+            suffix,
+        }
+    }
+
+    fn float(text: &str, value: f64, suffix: Option<NumberSuffix>) -> Token {
+        Token::Float {
+            text: text.into(),
+            value,
+            suffix,
+        }
+    }
+
+    fn lex(source: &str) -> Vec<Token> {
         let lexer = Lexer::new(source);
         let mut tokens: Vec<Token> = vec![];
         for token in lexer {
             tokens.push(token.value);
         }
+        assert_eq!(tokens.pop(), Some(Token::Eof));
         tokens
     }
 
+    fn lex_errors(source: &str) -> Vec<(LexErrorKind, Span)> {
+        let (_, errors) = Lexer::new(source).lex_all();
+        errors.into_iter().map(|e| (e.kind, e.span)).collect()
+    }
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn test_eof() {
+        let mut lexer = Lexer::new("a  ");
+        lexer.next();
+        assert_eq!(
+            lexer.next(),
+            Some(WithSpan {
+                value: Token::Eof,
+                span: Span { start: 3, end: 3 },
+            })
+        );
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lex(""), vec![]);
+    }
+
+    #[test]
+    fn test_interpolation_depth_limit() {
+        let nested = |depth: usize| "\"#{".repeat(depth) + &"}\"".repeat(depth);
+        assert!(matches!(
+            lex(&nested(MAX_INTERPOLATION_DEPTH))[..],
+            [Token::InterpolatedString(_)]
+        ));
+        assert!(lex_errors(&nested(MAX_INTERPOLATION_DEPTH)).is_empty());
+
+        // Reported once, not again for every enclosing string
+        let source = nested(MAX_INTERPOLATION_DEPTH + 1);
+        let start = 3 * MAX_INTERPOLATION_DEPTH;
+        assert_eq!(
+            lex_errors(&source),
+            vec![(LexErrorKind::NestingTooDeep, span(start, source.len()))]
+        );
+        // Deep enough to overflow the stack without the limit
+        let (tokens, errors) = Lexer::new(&nested(100_000)).lex_all();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(errors.len(), 1);
+    }
+
+    // Cheap deterministic generator so the test needs no dependencies
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_input() {
+        let alphabet: Vec<char> = "ab_19xo.-+*#{}()[]\"'\\?:=<>!&|^~%;, \n\té€λ\u{a0}\0"
+            .chars()
+            .collect();
+        let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2_000 {
+            let len = (rng.next() % 64) as usize;
+            let source: String = (0..len)
+                .map(|_| alphabet[(rng.next() % alphabet.len() as u64) as usize])
+                .collect();
+
+            let (tokens, errors) = Lexer::new(&source).lex_all();
+            assert_eq!(tokens.last().map(|t| &t.value), Some(&Token::Eof));
+            for error in &errors {
+                assert!(error.span.start <= error.span.end, "{source:?}: {error:?}");
+                assert!(source.is_char_boundary(error.span.start));
+                assert!(source.is_char_boundary(error.span.end));
+            }
+            let mut prev_end = 0;
+            for token in &tokens {
+                assert!(token.span.start >= prev_end, "{source:?}: {token:?}");
+                assert!(token.span.start <= token.span.end);
+                assert!(source.is_char_boundary(token.span.start));
+                assert!(source.is_char_boundary(token.span.end));
+                prev_end = token.span.end;
+            }
+            assert_eq!(prev_end, source.len());
+        }
+    }
+
     #[test]
     fn test_operators() {
         let tokens = lex("& && -> ! ^ = == >= > <= < << - != || % | + .. ..= >> / * ~");
@@ -342,6 +867,60 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_try_operator() {
+        let tokens = lex("read_file(path)?;");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("read_file".into()),
+                Token::LParen,
+                Token::Identifier("path".into()),
+                Token::RParen,
+                Token::Question,
+                Token::Semicolon
+            ]
+        );
+    }
+
+    #[test]
+    fn test_optional_operators() {
+        let tokens = lex("user?.address?.city ?? default? ?");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("user".into()),
+                Token::QuestionDot,
+                Token::Identifier("address".into()),
+                Token::QuestionDot,
+                Token::Identifier("city".into()),
+                Token::DoubleQuestion,
+                Token::Identifier("default".into()),
+                Token::Question,
+                Token::Question
+            ]
+        );
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let tokens = lex("2 ** 3 * 4 *** * *");
+        assert_eq!(
+            tokens,
+            vec![
+                int("2", 2, None),
+                Token::StarStar,
+                int("3", 3, None),
+                Token::Star,
+                int("4", 4, None),
+                Token::StarStar,
+                Token::Star,
+                Token::Star,
+                Token::Star
+            ]
+        );
+    }
+
     #[test]
     fn test_punctuation() {
         let tokens = lex(": , . :: { [ ( ) ] } ;");
@@ -365,17 +944,164 @@ mod tests {
 
     #[test]
     fn test_numbers() {
-        let tokens = lex("10 -10 10.5 -10.5 11.");
+        let tokens = lex("10 -10 10.5 -10.5 11. 0..5 1.abs pair.0.len");
+        assert_eq!(
+            tokens,
+            vec![
+                int("10", 10, None),
+                Token::Minus,
+                int("10", 10, None),
+                float("10.5", 10.5, None),
+                Token::Minus,
+                float("10.5", 10.5, None),
+                float("11.", 11.0, None),
+                int("0", 0, None),
+                Token::RangeExclusive,
+                int("5", 5, None),
+                int("1", 1, None),
+                Token::Dot,
+                Token::Identifier("abs".into()),
+                Token::Identifier("pair".into()),
+                Token::Dot,
+                int("0", 0, None),
+                Token::Dot,
+                Token::Identifier("len".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_minus_is_never_part_of_a_literal() {
+        let tokens = lex("a-1 a - -1 x->1");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("a".into()),
+                Token::Minus,
+                int("1", 1, None),
+                Token::Identifier("a".into()),
+                Token::Minus,
+                Token::Minus,
+                int("1", 1, None),
+                Token::Identifier("x".into()),
+                Token::Arrow,
+                int("1", 1, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        let tokens = lex(
+            "-9223372036854775808 18446744073709551615 99999999999999999999 0x1_0000_0000_0000_0000 1",
+        );
         assert_eq!(
             tokens,
             vec![
-                Token::Int(10),
-                Token::Int(-10),
-                Token::Float(10.5),
-                Token::Float(-10.5),
-                Token::Float(11.0)
+                Token::Minus,
+                int("9223372036854775808", i64::MIN.unsigned_abs(), None),
+                int("18446744073709551615", u64::MAX, None),
+                int("99999999999999999999", 0, None),
+                int("0x1_0000_0000_0000_0000", 0, None),
+                int("1", 1, None)
             ]
         );
+        assert_eq!(
+            lex_errors("1 99999999999999999999"),
+            vec![(LexErrorKind::IntegerOverflow, span(2, 22))]
+        );
+    }
+
+    #[test]
+    fn test_number_separators() {
+        let tokens = lex("1_000_000 -1_000 3_141.592_6 0xFF_FF 0b1010_1010 0o7_7");
+        assert_eq!(
+            tokens,
+            vec![
+                int("1_000_000", 1_000_000, None),
+                Token::Minus,
+                int("1_000", 1_000, None),
+                float("3_141.592_6", 3_141.592_6, None),
+                int("0xFF_FF", 0xFF_FF, None),
+                int("0b1010_1010", 0b1010_1010, None),
+                int("0o7_7", 0o7_7, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_separators() {
+        let tokens = lex("1__0 1_ 1_.5 1._5 0x_FF 0xFF_ 0x");
+        assert_eq!(
+            tokens,
+            vec![
+                int("1__0", 0, None),
+                int("1_", 0, None),
+                float("1_.5", 0.0, None),
+                float("1._5", 0.0, None),
+                int("0x_FF", 0, None),
+                int("0xFF_", 0, None),
+                int("0x", 0, None)
+            ]
+        );
+        assert_eq!(
+            lex_errors("1__0 0x"),
+            vec![
+                (LexErrorKind::InvalidNumber, span(0, 4)),
+                (LexErrorKind::InvalidNumber, span(5, 7))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_digits_out_of_range_for_radix() {
+        let tokens = lex("0b12 0o78 0b1_9u8");
+        assert_eq!(
+            tokens,
+            vec![
+                int("0b12", 0, None),
+                int("0o78", 0, None),
+                int("0b1_9u8", 0, None)
+            ]
+        );
+        assert_eq!(
+            lex_errors("0b12 0o78"),
+            vec![
+                (LexErrorKind::InvalidNumber, span(0, 4)),
+                (LexErrorKind::InvalidNumber, span(5, 9))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_suffixes() {
+        let tokens = lex("42i32 7u8 3.0f32 1f64 0xFFu16 1_000i64");
+        assert_eq!(
+            tokens,
+            vec![
+                int("42", 42, Some(NumberSuffix::I32)),
+                int("7", 7, Some(NumberSuffix::U8)),
+                float("3.0", 3.0, Some(NumberSuffix::F32)),
+                int("1", 1, Some(NumberSuffix::F64)),
+                int("0xFF", 0xFF, Some(NumberSuffix::U16)),
+                int("1_000", 1_000, Some(NumberSuffix::I64))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_number_suffixes() {
+        let tokens = lex("10px 3.0u8 1i7 1_u8");
+        assert_eq!(
+            tokens,
+            vec![
+                int("10px", 0, None),
+                float("3.0u8", 0.0, None),
+                int("1i7", 0, None),
+                int("1_u8", 0, None)
+            ]
+        );
+        assert_eq!(lex_errors("10px").len(), 1);
     }
 
     #[test]
@@ -413,7 +1139,11 @@ mod tests {
     #[test]
     fn test_unterminated_string() {
         let tokens = lex(r#""missing end"#);
-        assert_eq!(tokens, vec![Token::UnterminatedString]);
+        assert_eq!(tokens, vec![Token::String("missing end".into())]);
+        assert_eq!(
+            lex_errors(r#""missing end"#),
+            vec![(LexErrorKind::UnterminatedString, span(0, 12))]
+        );
     }
 
     #[test]
@@ -426,6 +1156,198 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_string_interpolation() {
+        let tokens: Vec<_> = Lexer::new(r#""Hey, #{name}!""#).collect();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(
+            tokens[0].value,
+            Token::InterpolatedString(vec![
+                StringPart::Text("Hey, ".into()),
+                StringPart::Interpolation(vec![
+                    WithSpan {
+                        value: Token::Identifier("name".into()),
+                        span: Span { start: 8, end: 12 },
+                    },
+                    WithSpan {
+                        value: Token::Eof,
+                        span: Span { start: 12, end: 12 },
+                    },
+                ]),
+                StringPart::Text("!".into()),
+            ])
+        );
+        assert_eq!(tokens[0].span, Span { start: 0, end: 15 });
+    }
+
+    #[test]
+    fn test_nested_interpolation() {
+        let tokens = lex(r##""#{ f({ "#{x}" }) }" after"##);
+        let Token::InterpolatedString(parts) = &tokens[0] else {
+            panic!("expected an interpolated string, got {:?}", tokens[0]);
+        };
+        let StringPart::Interpolation(inner) = &parts[0] else {
+            panic!("expected an interpolation, got {:?}", parts[0]);
+        };
+        let inner: Vec<_> = inner.iter().map(|t| t.value.clone()).collect();
+        assert_eq!(inner.len(), 7);
+        assert_eq!(inner[0], Token::Identifier("f".into()));
+        assert!(matches!(inner[3], Token::InterpolatedString(_)));
+        assert_eq!(inner[6], Token::Eof);
+        assert_eq!(tokens[1], Token::Identifier("after".into()));
+    }
+
+    #[test]
+    fn test_escaped_and_plain_hash() {
+        let tokens = lex(r##""\#{x} # {y} #""##);
+        assert_eq!(tokens, vec![Token::String("#{x} # {y} #".into())]);
+    }
+
+    #[test]
+    fn test_unterminated_interpolation() {
+        let (tokens, errors) = Lexer::new(r#""a #{ b"#).lex_all();
+        assert_eq!(
+            tokens[0].value,
+            Token::InterpolatedString(vec![
+                StringPart::Text("a ".into()),
+                StringPart::Interpolation(vec![
+                    WithSpan {
+                        value: Token::Identifier("b".into()),
+                        span: span(6, 7),
+                    },
+                    WithSpan {
+                        value: Token::Eof,
+                        span: span(7, 7),
+                    },
+                ]),
+            ])
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, LexErrorKind::UnterminatedString);
+        assert_eq!(errors[0].recovery.as_ref(), Some(&tokens[0].value));
+    }
+
+    #[test]
+    fn test_non_ascii_input() {
+        let tokens: Vec<_> = Lexer::new("\"héllo #{ñ}\" 'é'\u{a0}λ # ünïcode\nx").collect();
+        let values: Vec<_> = tokens.iter().map(|t| t.value.clone()).collect();
+        assert_eq!(
+            values,
+            vec![
+                Token::InterpolatedString(vec![
+                    StringPart::Text("héllo ".into()),
+                    StringPart::Interpolation(vec![WithSpan {
+                        value: Token::Eof,
+                        span: span(12, 12),
+                    }]),
+                ]),
+                Token::Char('é'),
+                Token::Comment(" ünïcode".into()),
+                Token::Identifier("x".into()),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            lex_errors("\"héllo #{ñ}\" 'é'\u{a0}λ"),
+            vec![
+                (LexErrorKind::UnknownChar('ñ'), span(10, 12)),
+                (LexErrorKind::UnknownChar('λ'), span(21, 23))
+            ]
+        );
+    }
+
+    fn assert_relex(source: &str, edit: TextEdit) {
+        let old_tokens: Vec<_> = Lexer::new(source).collect();
+        let edited = edit.apply(source);
+        let expected: Vec<_> = Lexer::new(&edited).collect();
+        assert_eq!(
+            Lexer::relex(&edited, &old_tokens, &edit),
+            expected,
+            "relexing {edited:?}"
+        );
+    }
+
+    #[test]
+    fn test_relex() {
+        let source = "fn main() {\n    let foo = \"a #{b + 1}\";\n    bar(foo) # done\n}";
+        let edits = [
+            (3, 7, "start"), // Rename `main`
+            (23, 23, "d"),   // Append to `foo`
+            (16, 20, ""),    // Delete a whole token
+            (24, 24, "\""),  // Open a string that swallows the rest
+            (31, 32, "c"),   // Edit inside an interpolation
+            (55, 55, "\n"),  // End the line comment early
+            (0, 0, "#* "),   // Comment everything out
+            (0, source.len(), ""),
+            (source.len(), source.len(), " x"),
+        ];
+        for (start, end, text) in edits {
+            assert_relex(
+                source,
+                TextEdit {
+                    span: Span { start, end },
+                    text: text.into(),
+                },
+            );
+        }
+
+        // Edits just past a token that change how it ends
+        let edits = [
+            ("1.a", 2, 3, "5"),   // `1`, `.`, `a` becomes `1.5`
+            ("x 1..2", 3, 4, ""), // `1`, `..`, `2` becomes `1.2`
+            ("a.0.b", 4, 5, "1"), // `0`, `.`, `b` becomes `0.1`
+            ("'ab x", 5, 5, "'"), // An unterminated char literal becomes an overlong one
+        ];
+        for (source, start, end, text) in edits {
+            assert_relex(
+                source,
+                TextEdit {
+                    span: Span { start, end },
+                    text: text.into(),
+                },
+            );
+        }
+
+        // Every single-character insertion and deletion
+        for start in 0..=source.len() {
+            for text in ["x", " ", "\"", "'", "#", "{", "}", "1", "."] {
+                assert_relex(
+                    source,
+                    TextEdit {
+                        span: Span { start, end: start },
+                        text: text.into(),
+                    },
+                );
+            }
+            if start < source.len() {
+                assert_relex(
+                    source,
+                    TextEdit {
+                        span: Span {
+                            start,
+                            end: start + 1,
+                        },
+                        text: String::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_relex_reuses_tokens() {
+        let source = "a b c d";
+        let old_tokens: Vec<_> = Lexer::new(source).collect();
+        let edit = TextEdit {
+            span: Span { start: 2, end: 3 },
+            text: "bee".into(),
+        };
+        let tokens = Lexer::relex(&edit.apply(source), &old_tokens, &edit);
+        assert_eq!(tokens[0], old_tokens[0]);
+        assert_eq!(tokens[1].value, Token::Identifier("bee".into()));
+        assert_eq!(tokens[3].span, Span { start: 8, end: 9 });
+    }
+
     #[test]
     fn test_valid_chars() {
         let tokens = lex(r#"'A' '1' ' '"#);
@@ -452,32 +1374,53 @@ mod tests {
     #[test]
     fn test_unterminated_char() {
         let tokens = lex(r#"'\n"#);
-        assert_eq!(tokens, vec![Token::UnterminatedChar]);
+        assert_eq!(tokens, vec![Token::Char('\n')]);
+        assert_eq!(
+            lex_errors("'a\nb"),
+            vec![(LexErrorKind::UnterminatedChar, span(0, 3))]
+        );
+        // An overlong literal that never closes runs to the end of the line
+        assert_eq!(
+            lex("'ab x\ny"),
+            vec![Token::Char('a'), Token::Identifier("y".into())]
+        );
+        assert_eq!(
+            lex_errors("'ab x\ny"),
+            vec![(LexErrorKind::UnterminatedChar, span(0, 5))]
+        );
     }
 
     #[test]
     fn test_invalid_char_literal() {
-        let tokens = lex(r#"'AB'"#);
+        let tokens = lex(r#"'AB' x"#);
         assert_eq!(
             tokens,
-            vec![Token::InvalidCharLiteral, Token::UnterminatedChar]
+            vec![Token::Char('A'), Token::Identifier("x".into())]
+        );
+        assert_eq!(
+            lex_errors(r#"'AB' x"#),
+            vec![(LexErrorKind::InvalidCharLiteral, span(0, 4))]
         );
     }
 
     #[test]
     fn test_identifiers() {
         let tokens = lex(
-            "break const continue enum fn for let loop match mod mut proto pub struct use while ident",
+            "as break const continue else enum fn for if in let loop match mod mut proto pub return self struct unless use while ident",
         );
         assert_eq!(
             tokens,
             vec![
+                Token::As,
                 Token::Break,
                 Token::Const,
                 Token::Continue,
+                Token::Else,
                 Token::Enum,
                 Token::Fn,
                 Token::For,
+                Token::If,
+                Token::In,
                 Token::Let,
                 Token::Loop,
                 Token::Match,
@@ -485,7 +1428,10 @@ mod tests {
                 Token::Mut,
                 Token::Proto,
                 Token::Pub,
+                Token::Return,
+                Token::SelfKw,
                 Token::Struct,
+                Token::Unless,
                 Token::Use,
                 Token::While,
                 Token::Identifier("ident".into())
@@ -493,6 +1439,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_keyword_prefixes_are_identifiers() {
+        let tokens = lex("iffy inner elsewhere unlessly");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("iffy".into()),
+                Token::Identifier("inner".into()),
+                Token::Identifier("elsewhere".into()),
+                Token::Identifier("unlessly".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_self_receiver() {
+        let tokens = lex("fn area(mut self) { self.w }");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Fn,
+                Token::Identifier("area".into()),
+                Token::LParen,
+                Token::Mut,
+                Token::SelfKw,
+                Token::RParen,
+                Token::LBrace,
+                Token::SelfKw,
+                Token::Dot,
+                Token::Identifier("w".into()),
+                Token::RBrace
+            ]
+        );
+    }
+
     #[test]
     fn test_booleans() {
         let tokens = lex("true false");
@@ -516,4 +1497,46 @@ mod tests {
             vec![Token::Comment(" This is\na multi-line\ncomment ".into())]
         );
     }
+
+    #[test]
+    fn test_unterminated_comment() {
+        let (tokens, errors) = Lexer::new("x #* open").lex_all();
+        assert_eq!(tokens[1].value, Token::Comment(" open".into()));
+        assert_eq!(
+            errors,
+            vec![LexError {
+                kind: LexErrorKind::UnterminatedComment,
+                span: span(2, 9),
+                recovery: Some(Token::Comment(" open".into())),
+            }]
+        );
+        assert_eq!(errors[0].to_string(), "unterminated comment");
+    }
+
+    #[test]
+    fn test_errors_do_not_stop_lexing() {
+        let (tokens, errors) = Lexer::new("let $ = 1__0; '").lex_all();
+        let values: Vec<_> = tokens.into_iter().map(|t| t.value).collect();
+        assert_eq!(
+            values,
+            vec![
+                Token::Let,
+                Token::Eq,
+                int("1__0", 0, None),
+                Token::Semicolon,
+                Token::Char(char::REPLACEMENT_CHARACTER),
+                Token::Eof
+            ]
+        );
+        let kinds: Vec<_> = errors.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                LexErrorKind::UnknownChar('$'),
+                LexErrorKind::InvalidNumber,
+                LexErrorKind::UnterminatedChar
+            ]
+        );
+        assert_eq!(errors[0].recovery, None);
+    }
 }
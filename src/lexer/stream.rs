@@ -0,0 +1,194 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, ErrorKind, Read},
+};
+
+use super::{LexError, Lexer, shift};
+use crate::token::{Token, WithSpan};
+
+/// Tokenizes a reader chunk by chunk instead of a whole `&str`. Only the input from the
+/// end of the last complete token is kept in memory, and spans are byte offsets into the
+/// whole stream, so the tokens match what `Lexer` produces for the same text.
+pub struct StreamLexer<R> {
+    reader: R,
+    buffer: Vec<u8>, // Input not lexed into a complete token yet
+    offset: usize,   // Stream offset of `buffer[0]`
+    // How long `buffer` has to get before it is lexed again. A token spanning many chunks
+    // would otherwise be lexed from its start once per chunk, taking quadratic time.
+    lex_at: usize,
+    pending: VecDeque<WithSpan<Token>>,
+    errors: Vec<LexError>,
+    finished: bool,
+}
+
+impl<R: Read> StreamLexer<BufReader<R>> {
+    pub fn from_reader(reader: R) -> Self {
+        Self::new(BufReader::new(reader))
+    }
+}
+
+impl<R: BufRead> StreamLexer<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: vec![],
+            offset: 0,
+            lex_at: 0,
+            pending: VecDeque::new(),
+            errors: vec![],
+            finished: false,
+        }
+    }
+
+    /// Errors found in the tokens produced so far.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    // Reads more input and queues every token that is known to be complete
+    fn fill(&mut self) -> io::Result<()> {
+        let at_end = loop {
+            match self.reader.fill_buf() {
+                Ok(chunk) => {
+                    let len = chunk.len();
+                    self.buffer.extend_from_slice(chunk);
+                    self.reader.consume(len);
+                    if len == 0 || self.buffer.len() >= self.lex_at {
+                        break len == 0;
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        };
+
+        let text = match std::str::from_utf8(&self.buffer) {
+            Ok(text) => text,
+            // The chunk ended in the middle of a character
+            Err(error) if error.error_len().is_none() && !at_end => {
+                std::str::from_utf8(&self.buffer[..error.valid_up_to()]).unwrap()
+            }
+            Err(error) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "stream did not contain valid UTF-8 at byte {}",
+                        self.offset + error.valid_up_to()
+                    ),
+                ));
+            }
+        };
+
+        // A token running up to the end of the text may continue in the next chunk, and
+        // the lexer may have looked past one that ends there. It is lexed again later.
+        let delta = self.offset as isize;
+        let mut lexer = Lexer::new(text);
+        let mut consumed = 0;
+        for token in lexer.by_ref() {
+            if !at_end && token.span.end >= text.len() {
+                break;
+            }
+            consumed = token.span.end;
+            self.pending.push_back(shift(&token, delta));
+        }
+        for mut error in lexer.errors {
+            if !at_end && error.span.end > consumed {
+                continue;
+            }
+            if let Some(recovery) = error.recovery {
+                let token = WithSpan {
+                    value: recovery,
+                    span: error.span,
+                };
+                let token = shift(&token, delta);
+                (error.recovery, error.span) = (Some(token.value), token.span);
+            } else {
+                error.span.start += self.offset;
+                error.span.end += self.offset;
+            }
+            self.errors.push(error);
+        }
+
+        self.buffer.drain(..consumed);
+        self.offset += consumed;
+        // Whatever is left is the start of an incomplete token, so wait for it to double
+        self.lex_at = self.buffer.len() * 2;
+        self.finished = at_end;
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for StreamLexer<R> {
+    type Item = io::Result<WithSpan<Token>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(Ok(token));
+            }
+            if self.finished {
+                return None;
+            }
+            if let Err(error) = self.fill() {
+                self.finished = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_lexer_for_any_chunk_size() {
+        let source = "fn main() -> int {\n  let s = \"héllo #{ f(x, \"#{y}\") }\"; # ünïcode\n  \
+                      #* multi\n line *# 1..2 + 3.5e 0xFFu8 'λ' 'ab' $ €\n  \
+                      'cd     e' 'fg  h\n}";
+        let (tokens, errors) = Lexer::new(source).lex_all();
+        for capacity in 1..=8 {
+            let mut stream =
+                StreamLexer::new(BufReader::with_capacity(capacity, source.as_bytes()));
+            let streamed: Vec<_> = stream.by_ref().map(Result::unwrap).collect();
+            assert_eq!(streamed, tokens, "chunks of {capacity} bytes");
+            assert_eq!(stream.errors(), errors, "chunks of {capacity} bytes");
+        }
+    }
+
+    #[test]
+    fn test_tokens_longer_than_chunks() {
+        let source = format!(
+            "a \"{}\" #* {} *# 1 # {}\nb",
+            "x".repeat(5000),
+            "é".repeat(3000),
+            "y".repeat(100)
+        );
+        let (tokens, errors) = Lexer::new(&source).lex_all();
+        for capacity in [1, 3, 64] {
+            let mut stream =
+                StreamLexer::new(BufReader::with_capacity(capacity, source.as_bytes()));
+            let streamed: Vec<_> = stream.by_ref().map(Result::unwrap).collect();
+            assert_eq!(streamed, tokens, "chunks of {capacity} bytes");
+            assert_eq!(stream.errors(), errors, "chunks of {capacity} bytes");
+        }
+    }
+
+    #[test]
+    fn test_empty_reader() {
+        let tokens: Vec<_> = StreamLexer::from_reader(io::empty()).collect();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].as_ref().unwrap().value, Token::Eof);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let bytes: &[u8] = b"let a = \"\xFF\";";
+        let results: Vec<_> = StreamLexer::new(BufReader::with_capacity(4, bytes)).collect();
+        let error = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("byte 9"));
+        // Tokens before the bad byte still come through
+        assert_eq!(results[0].as_ref().unwrap().value, Token::Let);
+    }
+}
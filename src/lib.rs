@@ -1,2 +1,7 @@
+pub mod ast;
+pub mod fingerprint;
 pub mod lexer;
+pub mod parser;
+pub mod source;
 pub mod token;
+pub mod version;
@@ -0,0 +1,1808 @@
+// Recursive descent parser producing a `Program`. Each method parses one production
+// from LANGUAGE.ebnf; expressions and patterns live in `parser/expr.rs`.
+
+mod expr;
+mod precedence;
+
+use std::fmt;
+
+use crate::{
+    ast::*,
+    token::{Span, Token, TokenStream, WithSpan},
+    version::{Feature, LanguageEdition},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Which syntax is accepted. Syntax behind a `Feature` the edition lacks is parsed as
+    /// if it didn't exist.
+    pub edition: LanguageEdition,
+    pub limits: ParserLimits,
+}
+
+/// Bounds on the input the parser accepts, so that untrusted input gets a `ParseError`
+/// instead of overflowing the stack.
+#[derive(Debug, Clone, Copy)]
+pub struct ParserLimits {
+    /// How deeply expressions, blocks, patterns, types and inline modules may nest inside
+    /// each other. Parentheses, blocks and operands each count as a level, so a block nested
+    /// in another one's statement takes two.
+    pub max_depth: usize,
+}
+
+impl Default for ParserLimits {
+    // Leaves room on the 2 MiB stack of a spawned thread, even in a debug build
+    fn default() -> Self {
+        Self { max_depth: 64 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub expected: String, // e.g. "`;`" or "type"
+    pub found: Token,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found)
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+pub struct Parser {
+    tokens: TokenStream,
+    pos: usize,
+    // Set while parsing `if`, `while`, `for` and `match` heads, where `x {` opens a block
+    no_struct_literal: bool,
+    errors: Vec<ParseError>, // Errors recovered from so far
+    next_id: usize,
+    edition: LanguageEdition,
+    limits: ParserLimits,
+    depth: usize, // How many `nested` calls are running
+}
+
+enum Parenthesized<T> {
+    Single(T),
+    Tuple(Vec<T>),
+}
+
+// One entry of a block: a statement, or the final expression that closed it
+enum BlockEntry {
+    Statement(Statement),
+    Final(Expression),
+}
+
+impl Parser {
+    /// `tokens` is what the lexer produced, comments included. A missing `Token::Eof` is
+    /// added at the end.
+    pub fn new(tokens: TokenStream) -> Self {
+        Self::with_options(tokens, &ParseOptions::default())
+    }
+
+    pub fn with_options(mut tokens: TokenStream, options: &ParseOptions) -> Self {
+        if tokens.last().is_none_or(|t| t.value != Token::Eof) {
+            let end = tokens.last().map_or(0, |t| t.span.end);
+            tokens.push(WithSpan {
+                value: Token::Eof,
+                span: Span { start: end, end },
+            });
+        }
+        Self {
+            tokens,
+            pos: 0,
+            no_struct_literal: false,
+            errors: vec![],
+            next_id: 0,
+            edition: options.edition,
+            limits: options.limits,
+            depth: 0,
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, Vec<ParseError>> {
+        let (program, errors) = self.parse_all();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses the whole input, recovering from syntax errors instead of stopping at the
+    /// first one. Returns whatever could be parsed along with every error found; a broken
+    /// statement, member or item is left out of the program.
+    pub fn parse_all(&mut self) -> (Program, Vec<ParseError>) {
+        let mut items = vec![];
+        loop {
+            if let Some(text) = self.comment() {
+                items.push(TopLevel::Comment(text));
+                continue;
+            }
+            if self.at(&Token::Eof) {
+                let errors = std::mem::take(&mut self.errors);
+                return (Program { items }, errors);
+            }
+            let start = self.pos;
+            match self.top_level() {
+                Ok(item) => items.push(item),
+                Err(error) => self.recover(error, start, false),
+            }
+        }
+    }
+
+    // Comments are only kept where the AST has room for them, everywhere else they are
+    // skipped over.
+    fn current(&self) -> &WithSpan<Token> {
+        self.tokens[self.pos..]
+            .iter()
+            .find(|t| !matches!(t.value, Token::Comment(_)))
+            .unwrap_or(&self.tokens[self.tokens.len() - 1])
+    }
+
+    fn peek(&self) -> &Token {
+        &self.current().value
+    }
+
+    // The token `n` places after the current one, ignoring comments
+    fn peek_nth(&self, n: usize) -> &Token {
+        self.tokens[self.pos..]
+            .iter()
+            .filter(|t| !matches!(t.value, Token::Comment(_)))
+            .nth(n)
+            .map_or(&Token::Eof, |t| &t.value)
+    }
+
+    fn advance(&mut self) -> WithSpan<Token> {
+        while matches!(self.tokens[self.pos].value, Token::Comment(_)) {
+            self.pos += 1;
+        }
+        let token = self.tokens[self.pos].clone();
+        if token.value != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn at(&self, token: &Token) -> bool {
+        self.peek() == token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.at(token) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: Token) -> ParseResult<()> {
+        if self.eat(&token) {
+            return Ok(());
+        }
+        Err(self.error(token.to_string()))
+    }
+
+    // `>` may have been lexed together with the next character, as in `Maybe<Maybe<T>>`
+    fn expect_gt(&mut self) -> ParseResult<()> {
+        let rest = match self.peek() {
+            Token::Gt => return self.expect(Token::Gt),
+            Token::RShift => Token::Gt,
+            Token::Ge => Token::Eq,
+            _ => return Err(self.error("`>`")),
+        };
+        self.advance();
+        self.pos -= 1;
+        let token = &mut self.tokens[self.pos];
+        token.value = rest;
+        token.span.start += 1;
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Token::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error("identifier")),
+        }
+    }
+
+    fn comment(&mut self) -> Option<String> {
+        match &self.tokens[self.pos].value {
+            Token::Comment(text) => {
+                let text = text.clone();
+                self.pos += 1;
+                Some(text)
+            }
+            _ => None,
+        }
+    }
+
+    fn error(&self, expected: impl Into<String>) -> ParseError {
+        self.error_at(self.pos, expected)
+    }
+
+    // An error for the token at `pos`, or the first one after it that isn't a comment
+    fn error_at(&self, pos: usize, expected: impl Into<String>) -> ParseError {
+        let token = self.tokens[pos..]
+            .iter()
+            .find(|t| !matches!(t.value, Token::Comment(_)))
+            .unwrap_or(&self.tokens[self.tokens.len() - 1]);
+        ParseError {
+            expected: expected.into(),
+            found: token.value.clone(),
+            span: token.span.clone(),
+        }
+    }
+
+    // Syntax behind a feature is parsed as if it didn't exist in editions without it
+    fn supports(&self, feature: Feature) -> bool {
+        self.edition.supports(feature)
+    }
+
+    fn node_id(&mut self) -> NodeId {
+        let id = NodeId::new(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn new_expression(&mut self, kind: ExpressionKind) -> Expression {
+        Expression {
+            id: self.node_id(),
+            kind,
+        }
+    }
+
+    fn new_statement(&mut self, kind: StatementKind) -> Statement {
+        Statement {
+            id: self.node_id(),
+            kind,
+        }
+    }
+
+    fn new_pattern(&mut self, kind: PatternKind) -> Pattern {
+        Pattern {
+            id: self.node_id(),
+            kind,
+        }
+    }
+
+    fn new_type(&mut self, kind: TypeKind) -> Type {
+        Type {
+            id: self.node_id(),
+            kind,
+        }
+    }
+
+    // Records `error` and skips the rest of the broken statement, member or item: up to
+    // and including the next `;`, or up to the next keyword starting an item or `let`, or
+    // the `}` closing the enclosing block. Braces in between are skipped as a whole. If
+    // nothing was consumed since `start` at least one token is skipped, so the caller
+    // doesn't run into the same error again.
+    fn recover(&mut self, error: ParseError, start: usize, in_block: bool) {
+        self.errors.push(error);
+        let mut depth = 0usize;
+        loop {
+            let stuck = self.pos == start;
+            match self.peek() {
+                Token::Eof => return,
+                Token::RBrace if depth == 0 && in_block => return,
+                Token::RBrace => depth = depth.saturating_sub(1),
+                Token::LBrace => depth += 1,
+                Token::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                Token::Pub
+                | Token::Fn
+                | Token::Struct
+                | Token::Enum
+                | Token::Proto
+                | Token::Const
+                | Token::Mod
+                | Token::Use
+                | Token::Let
+                    if depth == 0 && !stuck =>
+                {
+                    return;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    // Parses one member of a `{ }` body, or one statement of a block, with `f`, recovering
+    // from an error in it. Only a missing `}` at the end of the input is passed on.
+    fn member<T>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<Option<T>> {
+        if self.at(&Token::Eof) {
+            return Err(self.error("`}`"));
+        }
+        let start = self.pos;
+        match f(self) {
+            Ok(member) => Ok(Some(member)),
+            Err(error) => {
+                self.recover(error, start, true);
+                Ok(None)
+            }
+        }
+    }
+
+    // Runs `f` one level of nesting deeper. Every recursive production goes through here,
+    // so the limit bounds the recursion depth.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth >= self.limits.max_depth {
+            let max = self.limits.max_depth;
+            return Err(self.error(format!("at most {max} levels of nesting")));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    // Runs `f` with struct literals allowed or not, restoring the previous setting after
+    fn with_struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        f: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        let previous = std::mem::replace(&mut self.no_struct_literal, !allowed);
+        let result = f(self);
+        self.no_struct_literal = previous;
+        result
+    }
+
+    // Parses `item, { ",", item }` up to and including `close`
+    fn comma_list<T>(
+        &mut self,
+        close: Token,
+        mut item: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        let mut items = vec![];
+        if self.eat(&close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if !self.eat(&Token::Comma) {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    // Everything after an opening `(`. A comma makes a tuple, as in `()`, `(x,)` and
+    // `(x, y)`, while `(x)` is just `x`.
+    fn parenthesized<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Parenthesized<T>> {
+        if self.eat(&Token::RParen) {
+            return Ok(Parenthesized::Tuple(vec![]));
+        }
+        let first = item(self)?;
+        if self.eat(&Token::RParen) {
+            return Ok(Parenthesized::Single(first));
+        }
+        if !self.eat(&Token::Comma) {
+            return Err(self.error("`)`"));
+        }
+        let mut items = vec![first];
+        if !self.eat(&Token::RParen) {
+            items.extend(self.comma_list(Token::RParen, item)?);
+        }
+        Ok(Parenthesized::Tuple(items))
+    }
+
+    fn top_level(&mut self) -> ParseResult<TopLevel> {
+        match self.peek() {
+            Token::Mod => {
+                self.advance();
+                let name = self.identifier()?;
+                let kind = if self.eat(&Token::LBrace) {
+                    ModKind::Inline(self.nested(Self::module_items)?)
+                } else if self.eat(&Token::Semicolon) {
+                    ModKind::External
+                } else {
+                    return Err(self.error("`;` or `{`"));
+                };
+                Ok(TopLevel::Mod(ModDeclaration {
+                    id: self.node_id(),
+                    name,
+                    kind,
+                }))
+            }
+            Token::Use => {
+                self.advance();
+                let tree = self.use_tree()?;
+                self.expect(Token::Semicolon)?;
+                Ok(TopLevel::Use(UseStatement {
+                    id: self.node_id(),
+                    tree,
+                }))
+            }
+            _ => self.item().map(TopLevel::Item),
+        }
+    }
+
+    // Everything after the `{` of an inline module
+    fn module_items(&mut self) -> ParseResult<Vec<TopLevel>> {
+        let mut items = vec![];
+        loop {
+            if let Some(text) = self.comment() {
+                items.push(TopLevel::Comment(text));
+                continue;
+            }
+            if self.eat(&Token::RBrace) {
+                return Ok(items);
+            }
+            items.extend(self.member(Self::top_level)?);
+        }
+    }
+
+    fn use_tree(&mut self) -> ParseResult<UseTree> {
+        let mut segments = vec![self.identifier()?];
+        while self.eat(&Token::DoubleColon) {
+            if self.eat(&Token::Star) {
+                return Ok(UseTree::Glob(Path { segments }));
+            }
+            if self.eat(&Token::LBrace) {
+                let trees = self.nested(|p| p.comma_list(Token::RBrace, Self::use_tree))?;
+                let prefix = Path { segments };
+                return Ok(UseTree::Group { prefix, trees });
+            }
+            segments.push(self.identifier()?);
+        }
+        let alias = if self.eat(&Token::As) {
+            Some(self.identifier()?)
+        } else {
+            None
+        };
+        let path = Path { segments };
+        Ok(UseTree::Name { path, alias })
+    }
+
+    fn item(&mut self) -> ParseResult<Item> {
+        let is_pub = self.eat(&Token::Pub);
+        match self.peek() {
+            Token::Proto => self.protocol(is_pub).map(Item::Protocol),
+            Token::Struct => self.structure(is_pub).map(Item::Struct),
+            Token::Enum => self.enumeration(is_pub).map(Item::Enum),
+            Token::Fn => self.function(is_pub).map(Item::Function),
+            Token::Const => self.constant(is_pub).map(Item::Const),
+            _ if is_pub => Err(self.error("item")),
+            _ => Err(self.error("item, `mod` or `use`")),
+        }
+    }
+
+    fn protocol(&mut self, is_pub: bool) -> ParseResult<ProtocolDefinition> {
+        self.expect(Token::Proto)?;
+        let name = self.identifier()?;
+        let generics = self.generic_params()?;
+        let parents = self.protocol_list()?;
+        self.expect(Token::LBrace)?;
+
+        let mut members = vec![];
+        loop {
+            if let Some(text) = self.comment() {
+                members.push(ProtocolMember::Comment(text));
+                continue;
+            }
+            if self.eat(&Token::RBrace) {
+                break;
+            }
+            let method = self.member(|p| {
+                let is_pub = p.eat(&Token::Pub);
+                let mut method = p.function_signature(is_pub)?;
+                if !p.eat(&Token::Semicolon) {
+                    method.body = Some(p.block()?);
+                }
+                Ok(method)
+            })?;
+            members.extend(method.map(ProtocolMember::Method));
+        }
+
+        Ok(ProtocolDefinition {
+            id: self.node_id(),
+            is_pub,
+            name,
+            generics,
+            parents,
+            members,
+        })
+    }
+
+    fn structure(&mut self, is_pub: bool) -> ParseResult<StructDefinition> {
+        self.expect(Token::Struct)?;
+        let name = self.identifier()?;
+        let protocols = self.protocol_list()?;
+        self.expect(Token::LBrace)?;
+
+        let mut members = vec![];
+        loop {
+            if let Some(text) = self.comment() {
+                members.push(StructMember::Comment(text));
+                continue;
+            }
+            if self.eat(&Token::RBrace) {
+                break;
+            }
+            let member = self.member(|p| {
+                let is_pub = p.eat(&Token::Pub);
+                if p.at(&Token::Fn) {
+                    return p.function(is_pub).map(StructMember::Method);
+                }
+                let name = p.identifier()?;
+                p.expect(Token::Colon)?;
+                let ty = p.ty()?;
+                p.expect(Token::Semicolon)?;
+                Ok(StructMember::Field(StructField {
+                    id: p.node_id(),
+                    is_pub,
+                    name,
+                    ty,
+                }))
+            })?;
+            members.extend(member);
+        }
+
+        Ok(StructDefinition {
+            id: self.node_id(),
+            is_pub,
+            name,
+            protocols,
+            members,
+        })
+    }
+
+    fn enumeration(&mut self, is_pub: bool) -> ParseResult<EnumDefinition> {
+        self.expect(Token::Enum)?;
+        let name = self.identifier()?;
+        let generics = self.generic_params()?;
+        self.expect(Token::LBrace)?;
+
+        let mut members = vec![];
+        loop {
+            if let Some(text) = self.comment() {
+                members.push(EnumMember::Comment(text));
+                continue;
+            }
+            if self.eat(&Token::RBrace) {
+                break;
+            }
+            let member = self.member(|p| {
+                if matches!(p.peek(), Token::Pub | Token::Fn) {
+                    let is_pub = p.eat(&Token::Pub);
+                    return p.function(is_pub).map(EnumMember::Method);
+                }
+                p.variant().map(EnumMember::Variant)
+            })?;
+            members.extend(member);
+        }
+
+        Ok(EnumDefinition {
+            id: self.node_id(),
+            is_pub,
+            name,
+            generics,
+            members,
+        })
+    }
+
+    fn variant(&mut self) -> ParseResult<EnumVariant> {
+        let name = self.identifier()?;
+        let payload = if self.eat(&Token::LParen) {
+            let ty = self.ty()?;
+            self.expect(Token::RParen)?;
+            Some(VariantPayload::Tuple(ty))
+        } else if self.eat(&Token::LBrace) {
+            let mut fields = vec![];
+            while !self.eat(&Token::RBrace) {
+                let name = self.identifier()?;
+                self.expect(Token::Colon)?;
+                let ty = self.ty()?;
+                self.expect(Token::Semicolon)?;
+                fields.push(VariantField {
+                    id: self.node_id(),
+                    name,
+                    ty,
+                });
+            }
+            Some(VariantPayload::Struct(fields))
+        } else {
+            None
+        };
+        self.expect(Token::Semicolon)?;
+        Ok(EnumVariant {
+            id: self.node_id(),
+            name,
+            payload,
+        })
+    }
+
+    fn function(&mut self, is_pub: bool) -> ParseResult<FunctionDefinition> {
+        let mut function = self.function_signature(is_pub)?;
+        function.body = Some(self.block()?);
+        Ok(function)
+    }
+
+    // Returns the function with no body
+    fn function_signature(&mut self, is_pub: bool) -> ParseResult<FunctionDefinition> {
+        self.expect(Token::Fn)?;
+        let name = self.identifier()?;
+        let generics = self.generic_params()?;
+        self.expect(Token::LParen)?;
+        let params = self.function_params()?;
+        let return_type = if self.eat(&Token::Arrow) {
+            Some(self.ty()?)
+        } else {
+            None
+        };
+        Ok(FunctionDefinition {
+            id: self.node_id(),
+            is_pub,
+            name,
+            generics,
+            params,
+            return_type,
+            body: None,
+        })
+    }
+
+    // Everything after the opening `(`
+    fn function_params(&mut self) -> ParseResult<FunctionParams> {
+        if self.eat(&Token::RParen) {
+            return Ok(FunctionParams::None);
+        }
+        if matches!(self.peek(), Token::SelfKw)
+            || matches!((self.peek(), self.peek_nth(1)), (Token::Mut, Token::SelfKw))
+        {
+            let mutable = self.eat(&Token::Mut);
+            self.expect(Token::SelfKw)?;
+            if self.eat(&Token::RParen) {
+                return Ok(FunctionParams::SelfOnly { mutable });
+            }
+            self.expect(Token::Comma)?;
+            let params = self.comma_list(Token::RParen, Self::parameter)?;
+            return Ok(FunctionParams::SelfWith { mutable, params });
+        }
+        let params = self.comma_list(Token::RParen, Self::parameter)?;
+        Ok(FunctionParams::List(params))
+    }
+
+    fn parameter(&mut self) -> ParseResult<Parameter> {
+        let name = self.identifier()?;
+        self.expect(Token::Colon)?;
+        let ty = self.ty()?;
+        Ok(Parameter {
+            id: self.node_id(),
+            name,
+            ty,
+        })
+    }
+
+    fn constant(&mut self, is_pub: bool) -> ParseResult<ConstDefinition> {
+        self.expect(Token::Const)?;
+        let name = self.identifier()?;
+        self.expect(Token::Colon)?;
+        let ty = self.ty()?;
+        self.expect(Token::Eq)?;
+        let value = self.expression()?;
+        self.expect(Token::Semicolon)?;
+        Ok(ConstDefinition {
+            id: self.node_id(),
+            is_pub,
+            name,
+            ty,
+            value,
+        })
+    }
+
+    fn protocol_list(&mut self) -> ParseResult<Vec<ProtocolRef>> {
+        let mut protocols = vec![];
+        if self.eat(&Token::Colon) {
+            protocols.push(self.protocol_ref()?);
+            while self.eat(&Token::Comma) {
+                protocols.push(self.protocol_ref()?);
+            }
+        }
+        Ok(protocols)
+    }
+
+    fn protocol_ref(&mut self) -> ParseResult<ProtocolRef> {
+        let name = self.identifier()?;
+        let generic_args = if self.eat(&Token::Lt) {
+            self.generic_args()?
+        } else {
+            vec![]
+        };
+        Ok(ProtocolRef {
+            id: self.node_id(),
+            name,
+            generic_args,
+        })
+    }
+
+    // Everything after the opening `<`
+    fn generic_args(&mut self) -> ParseResult<Vec<Type>> {
+        let mut args = vec![self.ty()?];
+        while self.eat(&Token::Comma) {
+            args.push(self.ty()?);
+        }
+        self.expect_gt()?;
+        Ok(args)
+    }
+
+    fn generic_params(&mut self) -> ParseResult<Option<GenericParams>> {
+        if !self.eat(&Token::Lt) {
+            return Ok(None);
+        }
+        let mut params = vec![];
+        loop {
+            let name = self.identifier()?;
+            let mut constraints = vec![];
+            if self.eat(&Token::Colon) {
+                constraints.push(self.protocol_ref()?);
+                while self.eat(&Token::Plus) {
+                    constraints.push(self.protocol_ref()?);
+                }
+            }
+            let default = if self.eat(&Token::Eq) {
+                Some(self.ty()?)
+            } else {
+                None
+            };
+            params.push(GenericParam {
+                id: self.node_id(),
+                name,
+                constraints,
+                default,
+            });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect_gt()?;
+        Ok(Some(GenericParams { params }))
+    }
+
+    fn ty(&mut self) -> ParseResult<Type> {
+        self.nested(Self::ty_inner)
+    }
+
+    fn ty_inner(&mut self) -> ParseResult<Type> {
+        if self.eat(&Token::LParen) {
+            return match self.parenthesized(Self::ty)? {
+                Parenthesized::Tuple(types) => Ok(self.new_type(TypeKind::Tuple(types))),
+                Parenthesized::Single(ty) => Ok(ty),
+            };
+        }
+        if self.eat(&Token::LBracket) {
+            let element = Box::new(self.ty()?);
+            self.expect(Token::RBracket)?;
+            return Ok(self.new_type(TypeKind::List(element)));
+        }
+        let name = match self.peek() {
+            Token::Identifier(_) => self.identifier()?,
+            _ => return Err(self.error("type")),
+        };
+        let kind = match name.as_str() {
+            "int" => TypeKind::Int,
+            "float" => TypeKind::Float,
+            "bool" => TypeKind::Bool,
+            "char" => TypeKind::Char,
+            "str" => TypeKind::Str,
+            "List" if self.eat(&Token::Lt) => {
+                let element = Box::new(self.ty()?);
+                self.expect_gt()?;
+                TypeKind::List(element)
+            }
+            _ if self.eat(&Token::Lt) => {
+                let args = self.generic_args()?;
+                TypeKind::Generic { name, args }
+            }
+            _ => TypeKind::Named(name),
+        };
+        Ok(self.new_type(kind))
+    }
+
+    fn block(&mut self) -> ParseResult<Block> {
+        self.expect(Token::LBrace)?;
+        self.nested(|parser| parser.with_struct_literals(true, Self::block_contents))
+    }
+
+    // Everything after the opening `{`
+    fn block_contents(&mut self) -> ParseResult<Block> {
+        let mut statements = vec![];
+        loop {
+            if let Some(text) = self.comment() {
+                statements.push(self.new_statement(StatementKind::Comment(text)));
+                continue;
+            }
+            if self.eat(&Token::RBrace) {
+                return Ok(Block {
+                    id: self.node_id(),
+                    statements,
+                    final_expr: None,
+                });
+            }
+            match self.member(Self::block_entry)? {
+                Some(BlockEntry::Statement(statement)) => statements.push(statement),
+                Some(BlockEntry::Final(expr)) => {
+                    return Ok(Block {
+                        id: self.node_id(),
+                        statements,
+                        final_expr: Some(Box::new(expr)),
+                    });
+                }
+                None => {}
+            }
+        }
+    }
+
+    // A statement, or the final expression along with the `}` after it
+    fn block_entry(&mut self) -> ParseResult<BlockEntry> {
+        let kind = match self.peek() {
+            Token::Let => StatementKind::Let(self.variable_definition()?),
+            Token::Break => {
+                self.advance();
+                StatementKind::Break(self.optional_value()?)
+            }
+            Token::Return => {
+                self.advance();
+                StatementKind::Return(self.optional_value()?)
+            }
+            Token::Continue => {
+                self.advance();
+                self.expect(Token::Semicolon)?;
+                StatementKind::Continue
+            }
+            // Like in Rust, `if a {} - 1` is a statement followed by `-1`
+            _ if self.at_block_like() => {
+                let expr = self.primary()?;
+                if self.eat(&Token::RBrace) {
+                    return Ok(BlockEntry::Final(expr));
+                }
+                self.eat(&Token::Semicolon);
+                StatementKind::Expression(expr)
+            }
+            _ => {
+                let start = self.pos;
+                let expr = self.expression()?;
+                if self.eat(&Token::RBrace) {
+                    return Ok(BlockEntry::Final(expr));
+                }
+                let kind = if self.eat(&Token::Eq) {
+                    let target = self.place(expr, start)?;
+                    let value = self.expression()?;
+                    StatementKind::Assign { target, value }
+                } else {
+                    StatementKind::Expression(expr)
+                };
+                self.expect(Token::Semicolon)?;
+                kind
+            }
+        };
+        Ok(BlockEntry::Statement(self.new_statement(kind)))
+    }
+
+    // The rest of a `break` or `return`
+    fn optional_value(&mut self) -> ParseResult<Option<Expression>> {
+        let value = if self.at(&Token::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect(Token::Semicolon)?;
+        Ok(value)
+    }
+
+    // The left side of an assignment, parsed as an expression starting at `start`
+    fn place(&self, expr: Expression, start: usize) -> ParseResult<Place> {
+        let kind = match expr.kind {
+            ExpressionKind::Identifier(name) => PlaceKind::Variable(name),
+            ExpressionKind::FieldAccess { expr, field } => PlaceKind::Field { expr, field },
+            ExpressionKind::TupleIndex { expr, index } => PlaceKind::TupleIndex { expr, index },
+            ExpressionKind::Index { expr, index } => PlaceKind::Index { expr, index },
+            _ => return Err(self.error_at(start, "variable, field or index to assign to")),
+        };
+        Ok(Place { id: expr.id, kind })
+    }
+
+    // Expressions ending in a block, which don't need a `;` to be used as statements
+    fn at_block_like(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::If
+                | Token::Unless
+                | Token::LBrace
+                | Token::Loop
+                | Token::For
+                | Token::While
+                | Token::Match
+        )
+    }
+
+    fn variable_definition(&mut self) -> ParseResult<VariableDefinition> {
+        self.expect(Token::Let)?;
+        let mutable = self.eat(&Token::Mut);
+        let name = self.identifier()?;
+        let ty = if self.eat(&Token::Colon) {
+            Some(self.ty()?)
+        } else {
+            None
+        };
+        self.expect(Token::Eq)?;
+        let value = self.expression()?;
+        self.expect(Token::Semicolon)?;
+        Ok(VariableDefinition {
+            id: self.node_id(),
+            mutable,
+            name,
+            ty,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // Debug output with the node ids left out
+    fn shape(node: &(impl fmt::Debug + ?Sized)) -> String {
+        let text = format!("{node:?}");
+        let mut shape = String::new();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("id: NodeId(") {
+            shape.push_str(&rest[..start]);
+            let end = start + rest[start..].find(')').unwrap() + 1;
+            rest = rest[end..].trim_start_matches(", ");
+        }
+        shape.push_str(rest);
+        shape
+    }
+
+    // Parsed trees have real node ids and the ones built by hand `NodeId::DUMMY`, so they
+    // are compared without them
+    #[track_caller]
+    pub(super) fn assert_same_tree(
+        parsed: &(impl fmt::Debug + ?Sized),
+        expected: &(impl fmt::Debug + ?Sized),
+    ) {
+        assert_eq!(shape(parsed), shape(expected));
+    }
+
+    pub(super) fn e(kind: ExpressionKind) -> Expression {
+        Expression {
+            id: NodeId::DUMMY,
+            kind,
+        }
+    }
+
+    fn stmt(kind: StatementKind) -> Statement {
+        Statement {
+            id: NodeId::DUMMY,
+            kind,
+        }
+    }
+
+    pub(super) fn pat(kind: PatternKind) -> Pattern {
+        Pattern {
+            id: NodeId::DUMMY,
+            kind,
+        }
+    }
+
+    pub(super) fn ty(kind: TypeKind) -> Type {
+        Type {
+            id: NodeId::DUMMY,
+            kind,
+        }
+    }
+
+    fn parse(source: &str) -> Result<Program, Vec<ParseError>> {
+        Parser::new(Lexer::new(source).collect()).parse_program()
+    }
+
+    fn items(source: &str) -> Vec<TopLevel> {
+        match parse(source) {
+            Ok(program) => program.items,
+            Err(errors) => panic!("{source:?}: {errors:?}"),
+        }
+    }
+
+    fn item(source: &str) -> Item {
+        match &items(source)[..] {
+            [TopLevel::Item(item)] => item.clone(),
+            other => panic!("expected a single item, got {other:?}"),
+        }
+    }
+
+    fn error(source: &str) -> ParseError {
+        match parse(source) {
+            Ok(program) => panic!("{source:?} parsed: {program:?}"),
+            Err(errors) => errors[0].clone(),
+        }
+    }
+
+    fn path(segments: &[&str]) -> Path {
+        Path {
+            segments: segments.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn named(name: &str) -> Type {
+        ty(TypeKind::Named(name.into()))
+    }
+
+    fn protocol_ref(name: &str, generic_args: Vec<Type>) -> ProtocolRef {
+        ProtocolRef {
+            id: NodeId::DUMMY,
+            name: name.into(),
+            generic_args,
+        }
+    }
+
+    fn empty_block() -> Block {
+        Block {
+            id: NodeId::DUMMY,
+            statements: vec![],
+            final_expr: None,
+        }
+    }
+
+    #[test]
+    fn test_empty_program() {
+        assert_eq!(items(""), vec![]);
+        assert_eq!(
+            Parser::new(vec![]).parse_program(),
+            Ok(Program { items: vec![] })
+        );
+    }
+
+    #[test]
+    fn test_mod_and_use() {
+        assert_same_tree(
+            &items("mod some_module;\nuse some_module::say_hello;"),
+            &vec![
+                TopLevel::Mod(ModDeclaration {
+                    id: NodeId::DUMMY,
+                    name: "some_module".into(),
+                    kind: ModKind::External,
+                }),
+                TopLevel::Use(UseStatement {
+                    id: NodeId::DUMMY,
+                    tree: UseTree::Name {
+                        path: path(&["some_module", "say_hello"]),
+                        alias: None,
+                    },
+                }),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_inline_modules() {
+        assert_same_tree(
+            &items("mod a {\n  # inner\n  use b::c;\n  mod d {}\n  const X: int = 1;\n}"),
+            &vec![TopLevel::Mod(ModDeclaration {
+                id: NodeId::DUMMY,
+                name: "a".into(),
+                kind: ModKind::Inline(vec![
+                    TopLevel::Comment(" inner".into()),
+                    TopLevel::Use(UseStatement {
+                        id: NodeId::DUMMY,
+                        tree: UseTree::Name {
+                            path: path(&["b", "c"]),
+                            alias: None,
+                        },
+                    }),
+                    TopLevel::Mod(ModDeclaration {
+                        id: NodeId::DUMMY,
+                        name: "d".into(),
+                        kind: ModKind::Inline(vec![]),
+                    }),
+                    TopLevel::Item(Item::Const(ConstDefinition {
+                        id: NodeId::DUMMY,
+                        is_pub: false,
+                        name: "X".into(),
+                        ty: ty(TypeKind::Int),
+                        value: e(ExpressionKind::Literal(Literal::Integer("1".into(), None))),
+                    })),
+                ]),
+            })],
+        );
+        assert_eq!(error_message("mod a {"), "expected `}`, found end of file");
+
+        // An error inside the module doesn't end it
+        let (program, errors) =
+            Parser::new(Lexer::new("mod a { let x; use b; }\nmod c;").collect()).parse_all();
+        assert_eq!(errors.len(), 1);
+        let [TopLevel::Mod(a), TopLevel::Mod(c)] = &program.items[..] else {
+            panic!("expected two modules, got {program:?}");
+        };
+        assert!(matches!(&a.kind, ModKind::Inline(items) if items.len() == 1));
+        assert_eq!(c.name, "c");
+    }
+
+    #[test]
+    fn test_use_trees() {
+        let use_tree = |source: &str| match items(source).pop() {
+            Some(TopLevel::Use(statement)) => statement.tree,
+            other => panic!("{source:?} parsed to {other:?}"),
+        };
+        assert_eq!(
+            use_tree("use very::long::name as short;"),
+            UseTree::Name {
+                path: path(&["very", "long", "name"]),
+                alias: Some("short".into()),
+            }
+        );
+        assert_eq!(use_tree("use math::*;"), UseTree::Glob(path(&["math"])));
+        assert_eq!(
+            use_tree("use math::{sin, trig::cos as c, consts::*, geo::{}};"),
+            UseTree::Group {
+                prefix: path(&["math"]),
+                trees: vec![
+                    UseTree::Name {
+                        path: path(&["sin"]),
+                        alias: None,
+                    },
+                    UseTree::Name {
+                        path: path(&["trig", "cos"]),
+                        alias: Some("c".into()),
+                    },
+                    UseTree::Glob(path(&["consts"])),
+                    UseTree::Group {
+                        prefix: path(&["geo"]),
+                        trees: vec![],
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            error("use math::{sin cos};").to_string(),
+            "expected `}`, found identifier `cos`"
+        );
+        assert_eq!(
+            error("use *;").to_string(),
+            "expected identifier, found `*`"
+        );
+        assert_eq!(
+            error("use a::* as b;").to_string(),
+            "expected `;`, found `as`"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_same_tree(
+            &items("# one\n#* two *#\nmod a;"),
+            &vec![
+                TopLevel::Comment(" one".into()),
+                TopLevel::Comment(" two ".into()),
+                TopLevel::Mod(ModDeclaration {
+                    id: NodeId::DUMMY,
+                    name: "a".into(),
+                    kind: ModKind::External,
+                }),
+            ],
+        );
+        // Comments where the AST can't hold them are skipped
+        assert_eq!(items("mod # name\n a;").len(), 1);
+    }
+
+    #[test]
+    fn test_struct() {
+        let source = "pub struct Point : Comparable, Addable<Point> {
+            # Coordinates
+            pub x: float;
+            y: float;
+
+            fn norm(self) -> float { self.x }
+        }";
+        assert_same_tree(
+            &item(source),
+            &Item::Struct(StructDefinition {
+                id: NodeId::DUMMY,
+                is_pub: true,
+                name: "Point".into(),
+                protocols: vec![
+                    protocol_ref("Comparable", vec![]),
+                    protocol_ref("Addable", vec![named("Point")]),
+                ],
+                members: vec![
+                    StructMember::Comment(" Coordinates".into()),
+                    StructMember::Field(StructField {
+                        id: NodeId::DUMMY,
+                        is_pub: true,
+                        name: "x".into(),
+                        ty: ty(TypeKind::Float),
+                    }),
+                    StructMember::Field(StructField {
+                        id: NodeId::DUMMY,
+                        is_pub: false,
+                        name: "y".into(),
+                        ty: ty(TypeKind::Float),
+                    }),
+                    StructMember::Method(FunctionDefinition {
+                        id: NodeId::DUMMY,
+                        is_pub: false,
+                        name: "norm".into(),
+                        generics: None,
+                        params: FunctionParams::SelfOnly { mutable: false },
+                        return_type: Some(ty(TypeKind::Float)),
+                        body: Some(Block {
+                            id: NodeId::DUMMY,
+                            statements: vec![],
+                            final_expr: Some(Box::new(e(ExpressionKind::FieldAccess {
+                                expr: Box::new(e(ExpressionKind::SelfRef)),
+                                field: "x".into(),
+                            }))),
+                        }),
+                    }),
+                ],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_enum() {
+        let source = "enum Shape<T> {
+            Empty;
+            Circle(T);
+            Rect { w: T; h: T; };
+            pub fn area(self) -> T { self.w }
+        }";
+        let Item::Enum(definition) = item(source) else {
+            panic!("expected an enum");
+        };
+        assert_eq!(definition.name, "Shape");
+        assert_eq!(definition.generics.unwrap().params[0].name, "T");
+        assert_same_tree(
+            &definition.members[..3],
+            &[
+                EnumMember::Variant(EnumVariant {
+                    id: NodeId::DUMMY,
+                    name: "Empty".into(),
+                    payload: None,
+                }),
+                EnumMember::Variant(EnumVariant {
+                    id: NodeId::DUMMY,
+                    name: "Circle".into(),
+                    payload: Some(VariantPayload::Tuple(named("T"))),
+                }),
+                EnumMember::Variant(EnumVariant {
+                    id: NodeId::DUMMY,
+                    name: "Rect".into(),
+                    payload: Some(VariantPayload::Struct(vec![
+                        VariantField {
+                            id: NodeId::DUMMY,
+                            name: "w".into(),
+                            ty: named("T"),
+                        },
+                        VariantField {
+                            id: NodeId::DUMMY,
+                            name: "h".into(),
+                            ty: named("T"),
+                        },
+                    ])),
+                }),
+            ],
+        );
+        assert!(matches!(
+            &definition.members[3],
+            EnumMember::Method(FunctionDefinition { is_pub: true, name, .. }) if name == "area"
+        ));
+    }
+
+    #[test]
+    fn test_protocol() {
+        let source = "proto Comparable<Rhs = Self> : Equatable<Rhs> {
+            fn cmp(self, other: Rhs) -> Maybe<Ordering>;
+            fn ne(self, other: Rhs) -> bool { !self.eq(other) }
+        }";
+        let Item::Protocol(definition) = item(source) else {
+            panic!("expected a protocol");
+        };
+        assert_same_tree(
+            &definition.generics,
+            &Some(GenericParams {
+                params: vec![GenericParam {
+                    id: NodeId::DUMMY,
+                    name: "Rhs".into(),
+                    constraints: vec![],
+                    default: Some(named("Self")),
+                }],
+            }),
+        );
+        assert_same_tree(
+            &definition.parents,
+            &vec![protocol_ref("Equatable", vec![named("Rhs")])],
+        );
+        let [ProtocolMember::Method(cmp), ProtocolMember::Method(ne)] = &definition.members[..]
+        else {
+            panic!("expected two methods, got {:?}", definition.members);
+        };
+        assert_same_tree(
+            &cmp.return_type,
+            &Some(ty(TypeKind::Generic {
+                name: "Maybe".into(),
+                args: vec![named("Ordering")],
+            })),
+        );
+        assert_eq!(cmp.body, None);
+        assert!(ne.body.is_some());
+    }
+
+    #[test]
+    fn test_function() {
+        let source = "fn max<T: Ord + Show<T>>(a: T, b: (int, str)) -> T {}";
+        assert_same_tree(
+            &item(source),
+            &Item::Function(FunctionDefinition {
+                id: NodeId::DUMMY,
+                is_pub: false,
+                name: "max".into(),
+                generics: Some(GenericParams {
+                    params: vec![GenericParam {
+                        id: NodeId::DUMMY,
+                        name: "T".into(),
+                        constraints: vec![
+                            protocol_ref("Ord", vec![]),
+                            protocol_ref("Show", vec![named("T")]),
+                        ],
+                        default: None,
+                    }],
+                }),
+                params: FunctionParams::List(vec![
+                    Parameter {
+                        id: NodeId::DUMMY,
+                        name: "a".into(),
+                        ty: named("T"),
+                    },
+                    Parameter {
+                        id: NodeId::DUMMY,
+                        name: "b".into(),
+                        ty: ty(TypeKind::Tuple(vec![ty(TypeKind::Int), ty(TypeKind::Str)])),
+                    },
+                ]),
+                return_type: Some(named("T")),
+                body: Some(empty_block()),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_function_params() {
+        let params = |source: &str| match item(source) {
+            Item::Function(function) => function.params,
+            other => panic!("expected a function, got {other:?}"),
+        };
+        assert_eq!(params("fn f() {}"), FunctionParams::None);
+        assert_eq!(
+            params("fn f(mut self) {}"),
+            FunctionParams::SelfOnly { mutable: true }
+        );
+        assert_same_tree(
+            &params("fn f(self, x: int) {}"),
+            &FunctionParams::SelfWith {
+                mutable: false,
+                params: vec![Parameter {
+                    id: NodeId::DUMMY,
+                    name: "x".into(),
+                    ty: ty(TypeKind::Int),
+                }],
+            },
+        );
+        assert_eq!(error("fn f(x: int, self) {}").expected, "identifier");
+    }
+
+    #[test]
+    fn test_nested_generic_types() {
+        let source = "const X: Maybe<Maybe<int>> = x;";
+        let Item::Const(definition) = item(source) else {
+            panic!("expected a const");
+        };
+        assert_same_tree(
+            &definition.ty,
+            &ty(TypeKind::Generic {
+                name: "Maybe".into(),
+                args: vec![ty(TypeKind::Generic {
+                    name: "Maybe".into(),
+                    args: vec![ty(TypeKind::Int)],
+                })],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_multiple_generic_args() {
+        let source = "fn f<T: Into<str, int> = Map<str, Maybe<int>>>(m: Map<str, (int, T)>) {}";
+        let Item::Function(function) = item(source) else {
+            panic!("expected a function");
+        };
+        let param = &function.generics.unwrap().params[0];
+        assert_same_tree(
+            &param.constraints,
+            &[protocol_ref(
+                "Into",
+                vec![ty(TypeKind::Str), ty(TypeKind::Int)],
+            )],
+        );
+        assert_same_tree(
+            &param.default,
+            &Some(ty(TypeKind::Generic {
+                name: "Map".into(),
+                args: vec![
+                    ty(TypeKind::Str),
+                    ty(TypeKind::Generic {
+                        name: "Maybe".into(),
+                        args: vec![ty(TypeKind::Int)],
+                    }),
+                ],
+            })),
+        );
+        let FunctionParams::List(params) = function.params else {
+            panic!("expected a parameter list");
+        };
+        assert_same_tree(
+            &params[0].ty,
+            &ty(TypeKind::Generic {
+                name: "Map".into(),
+                args: vec![
+                    ty(TypeKind::Str),
+                    ty(TypeKind::Tuple(vec![ty(TypeKind::Int), named("T")])),
+                ],
+            }),
+        );
+        assert_eq!(
+            error_message("const X: Map<> = x;"),
+            "expected type, found `>`"
+        );
+    }
+
+    #[test]
+    fn test_tuple_and_list_types() {
+        let ty_of = |source: &str| match item(&format!("const X: {source} = x;")) {
+            Item::Const(definition) => definition.ty,
+            other => panic!("expected a const, got {other:?}"),
+        };
+        assert_same_tree(&ty_of("()"), &ty(TypeKind::Tuple(vec![])));
+        assert_same_tree(
+            &ty_of("(int,)"),
+            &ty(TypeKind::Tuple(vec![ty(TypeKind::Int)])),
+        );
+        assert_same_tree(&ty_of("(int)"), &ty(TypeKind::Int));
+        let list = ty(TypeKind::List(Box::new(ty(TypeKind::Str))));
+        assert_same_tree(&ty_of("[str]"), &list);
+        assert_same_tree(&ty_of("List<str>"), &list);
+        assert_same_tree(
+            &ty_of("[(int, List<T>)]"),
+            &ty(TypeKind::List(Box::new(ty(TypeKind::Tuple(vec![
+                ty(TypeKind::Int),
+                ty(TypeKind::List(Box::new(named("T")))),
+            ]))))),
+        );
+        assert_eq!(
+            error_message("const X: [int, str] = x;"),
+            "expected `]`, found `,`"
+        );
+    }
+
+    #[test]
+    fn test_const() {
+        assert_same_tree(
+            &item("pub const LIMIT: int = 10;"),
+            &Item::Const(ConstDefinition {
+                id: NodeId::DUMMY,
+                is_pub: true,
+                name: "LIMIT".into(),
+                ty: ty(TypeKind::Int),
+                value: e(ExpressionKind::Literal(Literal::Integer("10".into(), None))),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_block_statements() {
+        let Item::Function(function) = item(
+            "fn main() {
+                # start
+                let mut x: int = 1;
+                loop { break; }
+                while x { continue; }
+                f(x);
+                break x;
+                return;
+                return x + 1;
+                x
+            }",
+        ) else {
+            panic!("expected a function");
+        };
+        let body = function.body.unwrap();
+        let x = || e(ExpressionKind::Identifier("x".into()));
+        assert_same_tree(
+            &body.statements,
+            &vec![
+                stmt(StatementKind::Comment(" start".into())),
+                stmt(StatementKind::Let(VariableDefinition {
+                    id: NodeId::DUMMY,
+                    mutable: true,
+                    name: "x".into(),
+                    ty: Some(ty(TypeKind::Int)),
+                    value: e(ExpressionKind::Literal(Literal::Integer("1".into(), None))),
+                })),
+                stmt(StatementKind::Expression(e(ExpressionKind::Loop(Block {
+                    id: NodeId::DUMMY,
+                    statements: vec![stmt(StatementKind::Break(None))],
+                    final_expr: None,
+                })))),
+                stmt(StatementKind::Expression(e(ExpressionKind::While {
+                    condition: Box::new(x()),
+                    body: Block {
+                        id: NodeId::DUMMY,
+                        statements: vec![stmt(StatementKind::Continue)],
+                        final_expr: None,
+                    },
+                }))),
+                stmt(StatementKind::Expression(e(ExpressionKind::Call {
+                    callee: Box::new(e(ExpressionKind::Identifier("f".into()))),
+                    args: vec![x()],
+                }))),
+                stmt(StatementKind::Break(Some(x()))),
+                stmt(StatementKind::Return(None)),
+                stmt(StatementKind::Return(Some(e(ExpressionKind::Binary {
+                    left: Box::new(x()),
+                    op: BinaryOperator::Add,
+                    right: Box::new(e(ExpressionKind::Literal(Literal::Integer(
+                        "1".into(),
+                        None,
+                    )))),
+                })))),
+            ],
+        );
+        assert_same_tree(&body.final_expr, &Some(Box::new(x())));
+    }
+
+    #[test]
+    fn test_assignment() {
+        let Item::Function(function) = item("fn f() { x = 1; self.a.b = x + 1; xs[0] = x; }")
+        else {
+            panic!("expected a function");
+        };
+        let self_a = e(ExpressionKind::FieldAccess {
+            expr: Box::new(e(ExpressionKind::SelfRef)),
+            field: "a".into(),
+        });
+        assert_same_tree(
+            &function.body.unwrap().statements,
+            &[
+                stmt(StatementKind::Assign {
+                    target: Place {
+                        id: NodeId::DUMMY,
+                        kind: PlaceKind::Variable("x".into()),
+                    },
+                    value: e(ExpressionKind::Literal(Literal::Integer("1".into(), None))),
+                }),
+                stmt(StatementKind::Assign {
+                    target: Place {
+                        id: NodeId::DUMMY,
+                        kind: PlaceKind::Field {
+                            expr: Box::new(self_a),
+                            field: "b".into(),
+                        },
+                    },
+                    value: e(ExpressionKind::Binary {
+                        left: Box::new(e(ExpressionKind::Identifier("x".into()))),
+                        op: BinaryOperator::Add,
+                        right: Box::new(e(ExpressionKind::Literal(Literal::Integer(
+                            "1".into(),
+                            None,
+                        )))),
+                    }),
+                }),
+                stmt(StatementKind::Assign {
+                    target: Place {
+                        id: NodeId::DUMMY,
+                        kind: PlaceKind::Index {
+                            expr: Box::new(e(ExpressionKind::Identifier("xs".into()))),
+                            index: Box::new(e(ExpressionKind::Literal(Literal::Integer(
+                                "0".into(),
+                                None,
+                            )))),
+                        },
+                    },
+                    value: e(ExpressionKind::Identifier("x".into())),
+                }),
+            ],
+        );
+
+        let error = error("fn f() { 1 + a = 2; }");
+        assert_eq!(
+            error.to_string(),
+            "expected variable, field or index to assign to, found number `1`"
+        );
+        assert_eq!(error.span, Span { start: 9, end: 10 });
+        assert_eq!(
+            error_message("fn f() { f() = 2; }"),
+            "expected variable, field or index to assign to, found identifier `f`"
+        );
+        assert_eq!(error_message("fn f() { x = 1 }"), "expected `;`, found `}`");
+    }
+
+    #[test]
+    fn test_block_like_statements() {
+        let Item::Function(function) = item("fn f() { if a {} - 1 }") else {
+            panic!("expected a function");
+        };
+        let body = function.body.unwrap();
+        assert!(matches!(
+            &body.statements[..],
+            [Statement {
+                kind: StatementKind::Expression(Expression {
+                    kind: ExpressionKind::If { .. },
+                    ..
+                }),
+                ..
+            }]
+        ));
+        assert!(matches!(
+            body.final_expr.map(|expr| expr.kind),
+            Some(ExpressionKind::Unary {
+                op: UnaryOperator::Neg,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_node_ids() {
+        let source =
+            "struct P { x: int; }\nfn f(p: P) -> str { let y = p.x + 1; g(y); \"#{y}#{-y}\" }";
+        let program = parse(source).unwrap();
+        let text = format!("{program:?}");
+        let mut ids: Vec<usize> = text
+            .split("id: NodeId(")
+            .skip(1)
+            .map(|rest| rest[..rest.find(')').unwrap()].parse().unwrap())
+            .collect();
+        ids.sort();
+        // Interpolations share the counter, so every id is used once
+        assert_eq!(ids, (0..ids.len()).collect::<Vec<_>>());
+        assert!(!shape(&program).contains("NodeId"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = error("mod a");
+        assert_eq!(error.found, Token::Eof);
+        assert_eq!(error.span, Span { start: 5, end: 5 });
+        assert_eq!(error.to_string(), "expected `;` or `{`, found end of file");
+
+        assert_eq!(error_message("struct {}"), "expected identifier, found `{`");
+        assert_eq!(error_message("pub mod a;"), "expected item, found `mod`");
+        assert_eq!(
+            error_message("let x = 1;"),
+            "expected item, `mod` or `use`, found `let`"
+        );
+        assert_eq!(
+            error_message("fn f() { a b }"),
+            "expected `;`, found identifier `b`"
+        );
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let source = "struct {}\nfn f() {}\nconst X = 1;\nenum E { A; }";
+        let (program, errors) = Parser::new(Lexer::new(source).collect()).parse_all();
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            ["expected identifier, found `{`", "expected `:`, found `=`"]
+        );
+        let names: Vec<_> = program
+            .items
+            .iter()
+            .map(|item| match item {
+                TopLevel::Item(Item::Function(f)) => f.name.as_str(),
+                TopLevel::Item(Item::Enum(e)) => e.name.as_str(),
+                other => panic!("unexpected item {other:?}"),
+            })
+            .collect();
+        assert_eq!(names, ["f", "E"]);
+
+        let source = "struct S { a int; b: int; }\n} fn f() { let = 1; g(); a b; h() }";
+        let (program, errors) = Parser::new(Lexer::new(source).collect()).parse_all();
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        let [
+            TopLevel::Item(Item::Struct(s)),
+            TopLevel::Item(Item::Function(f)),
+        ] = &program.items[..]
+        else {
+            panic!("{program:?}");
+        };
+        assert_same_tree(
+            &s.members,
+            &[StructMember::Field(StructField {
+                id: NodeId::DUMMY,
+                is_pub: false,
+                name: "b".into(),
+                ty: ty(TypeKind::Int),
+            })],
+        );
+        let body = f.body.as_ref().unwrap();
+        assert_eq!(body.statements.len(), 1);
+        assert!(body.final_expr.is_some());
+
+        // Blocks inside interpolations recover too
+        let (_, errors) =
+            Parser::new(Lexer::new("fn f() { \"#{ { a b } }\"; }").collect()).parse_all();
+        assert_eq!(errors.len(), 1, "{errors:?}");
+    }
+
+    #[test]
+    fn test_recovery_always_finishes() {
+        let source = "pub struct S: P<T> { a: int; fn m(self) -> int { self.a } }\n\
+                      fn main() { let x = S { a: 1 }; if x.m() > 0 { g(|y| y + 1); } }";
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        // Most prefixes are cut off mid-item, but parsing them still has to end
+        for end in 0..tokens.len() {
+            Parser::new(tokens[..end].to_vec()).parse_all();
+        }
+        assert!(parse(source).is_ok());
+        assert_eq!(
+            error_message("fn f() { g();"),
+            "expected `}`, found end of file"
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        // Way past the limit, which has to be an error instead of a stack overflow
+        for source in [
+            format!("const X: int = {};", "(".repeat(10_000)),
+            format!("fn f() {{ {} }}", "if a { ".repeat(10_000)),
+            format!("const X: {}int = 1;", "[".repeat(10_000)),
+            "mod a { ".repeat(10_000),
+            format!("fn f() {{ if a {{}}{} }}", " else if a {}".repeat(10_000)),
+            format!("use {};", "a::{".repeat(10_000)),
+        ] {
+            let (_, errors) = Parser::new(Lexer::new(&source).collect()).parse_all();
+            assert_eq!(errors[0].expected, "at most 64 levels of nesting");
+        }
+        let error = error(&format!("const X: int = {}1 ** 2;", "-".repeat(100)));
+        assert_eq!(error.found, Token::Minus);
+
+        let options = ParseOptions {
+            limits: ParserLimits { max_depth: 3 },
+            ..ParseOptions::default()
+        };
+        let parse = |source: &str| {
+            Parser::with_options(Lexer::new(source).collect(), &options)
+                .expression()
+                .map_err(|e| e.to_string())
+        };
+        assert!(parse("((a))").is_ok());
+        assert_eq!(
+            parse("(((a)))"),
+            Err("expected at most 3 levels of nesting, found identifier `a`".into())
+        );
+        // Interpolations count the levels around them
+        assert!(parse("(\"#{a}\")").is_ok());
+        assert!(parse("((\"#{a}\"))").is_err());
+    }
+
+    fn error_message(source: &str) -> String {
+        error(source).to_string()
+    }
+}
@@ -0,0 +1,1111 @@
+// Expressions and match patterns. Infix operators go through a Pratt loop driven by the
+// table in `precedence.rs`; prefix and postfix operators are parsed directly.
+
+use super::{
+    Parenthesized, ParseResult, Parser,
+    precedence::{Infix, Precedence},
+};
+use crate::{
+    ast::*,
+    token::{StringPart, Token},
+    version::Feature,
+};
+
+impl Parser {
+    pub(super) fn expression(&mut self) -> ParseResult<Expression> {
+        self.binary(0)
+    }
+
+    // The head of `if`, `while`, `for` and `match`, which is directly followed by a `{`
+    fn condition(&mut self) -> ParseResult<Expression> {
+        self.with_struct_literals(false, Self::expression)
+    }
+
+    // An expression between delimiters, where a `{` can't be mistaken for a block
+    fn delimited(&mut self) -> ParseResult<Expression> {
+        self.with_struct_literals(true, Self::expression)
+    }
+
+    // Parses an expression whose infix operators all bind at least as tight as `min_power`
+    fn binary(&mut self, min_power: u8) -> ParseResult<Expression> {
+        self.nested(|parser| parser.binary_inner(min_power))
+    }
+
+    fn binary_inner(&mut self, min_power: u8) -> ParseResult<Expression> {
+        let mut left = self.unary()?;
+        let mut ranged = false;
+        while let Some(infix) = Infix::from_token(self.peek()) {
+            let (left_power, right_power) = infix.binding_power();
+            let is_range = matches!(infix, Infix::Range { .. });
+            // Ranges don't chain, `a..b..c` stops after `b`
+            if left_power < min_power || (is_range && ranged) {
+                break;
+            }
+            self.advance();
+            let right = self.binary(right_power)?;
+            left = self.new_expression(infix.apply(left, right));
+            ranged |= is_range;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> ParseResult<Expression> {
+        let op = match self.peek() {
+            Token::Minus => UnaryOperator::Neg,
+            Token::Bang => UnaryOperator::Not,
+            Token::Tilde => UnaryOperator::BitNot,
+            _ => return self.postfix(),
+        };
+        self.advance();
+        let expr = Box::new(self.binary(Precedence::Prefix.power())?);
+        Ok(self.new_expression(ExpressionKind::Unary { op, expr }))
+    }
+
+    fn postfix(&mut self) -> ParseResult<Expression> {
+        let mut expr = self.primary()?;
+        loop {
+            let kind = match self.peek() {
+                Token::LParen => {
+                    self.advance();
+                    let args = self.arguments()?;
+                    ExpressionKind::Call {
+                        callee: Box::new(expr),
+                        args,
+                    }
+                }
+                Token::Dot => {
+                    self.advance();
+                    if matches!(self.peek(), Token::Int { .. } | Token::Float { .. }) {
+                        let indices = self.tuple_indices()?;
+                        for index in indices {
+                            let kind = ExpressionKind::TupleIndex {
+                                expr: Box::new(expr),
+                                index,
+                            };
+                            expr = self.new_expression(kind);
+                        }
+                        continue;
+                    }
+                    let name = self.identifier()?;
+                    if self.eat(&Token::LParen) {
+                        let args = self.arguments()?;
+                        ExpressionKind::MethodCall {
+                            receiver: Box::new(expr),
+                            method: name,
+                            args,
+                        }
+                    } else {
+                        ExpressionKind::FieldAccess {
+                            expr: Box::new(expr),
+                            field: name,
+                        }
+                    }
+                }
+                Token::QuestionDot => {
+                    self.advance();
+                    let field = self.identifier()?;
+                    ExpressionKind::OptionalFieldAccess {
+                        expr: Box::new(expr),
+                        field,
+                    }
+                }
+                Token::Question => {
+                    self.advance();
+                    ExpressionKind::Try(Box::new(expr))
+                }
+                Token::LBracket => {
+                    self.advance();
+                    let index = Box::new(self.delimited()?);
+                    self.expect(Token::RBracket)?;
+                    ExpressionKind::Index {
+                        expr: Box::new(expr),
+                        index,
+                    }
+                }
+                _ => return Ok(expr),
+            };
+            expr = self.new_expression(kind);
+        }
+    }
+
+    // `0` in `pair.0`. The indices in `pair.0.1` are lexed as a float, which is split
+    // back apart here. Each one has to be written as plain decimal digits with no leading
+    // zero, so `pair.01` and `pair.0_1` are errors rather than another way to write `1`.
+    fn tuple_indices(&mut self) -> ParseResult<Vec<usize>> {
+        let indices = match self.peek() {
+            Token::Int {
+                text, suffix: None, ..
+            }
+            | Token::Float {
+                text, suffix: None, ..
+            } => text
+                .split('.')
+                .map(|index| {
+                    let digits = index.bytes().all(|x| x.is_ascii_digit());
+                    let canonical = digits && (index == "0" || !index.starts_with('0'));
+                    if canonical { index.parse().ok() } else { None }
+                })
+                .collect(),
+            _ => None,
+        };
+        let Some(indices) = indices else {
+            return Err(self.error("tuple index"));
+        };
+        self.advance();
+        Ok(indices)
+    }
+
+    // Everything after the opening `(`
+    fn arguments(&mut self) -> ParseResult<Vec<Expression>> {
+        self.comma_list(Token::RParen, Self::delimited)
+    }
+
+    pub(super) fn primary(&mut self) -> ParseResult<Expression> {
+        let kind = match self.peek() {
+            Token::Int { .. }
+            | Token::Float { .. }
+            | Token::String(_)
+            | Token::InterpolatedString(_)
+            | Token::Char(_)
+            | Token::Bool(_) => ExpressionKind::Literal(self.literal()?),
+            Token::SelfKw => {
+                self.advance();
+                ExpressionKind::SelfRef
+            }
+            Token::Identifier(_) => return self.path_expression(),
+            Token::LParen => {
+                self.advance();
+                match self.parenthesized(Self::delimited)? {
+                    Parenthesized::Tuple(elements) => ExpressionKind::Tuple(elements),
+                    Parenthesized::Single(expr) => return Ok(expr),
+                }
+            }
+            Token::LBrace => ExpressionKind::Block(self.block()?),
+            Token::LBracket => {
+                self.advance();
+                let elements = self.comma_list(Token::RBracket, Self::delimited)?;
+                ExpressionKind::List(elements)
+            }
+            Token::If => return self.if_expression(),
+            Token::Unless => {
+                self.advance();
+                let condition = Box::new(self.condition()?);
+                let then_block = self.block()?;
+                let else_block = if self.eat(&Token::Else) {
+                    Some(self.block()?)
+                } else {
+                    None
+                };
+                ExpressionKind::Unless {
+                    condition,
+                    then_block,
+                    else_block,
+                }
+            }
+            Token::Loop => {
+                self.advance();
+                ExpressionKind::Loop(self.block()?)
+            }
+            Token::While => {
+                self.advance();
+                let condition = Box::new(self.condition()?);
+                let body = self.block()?;
+                ExpressionKind::While { condition, body }
+            }
+            Token::For => {
+                self.advance();
+                let var = self.identifier()?;
+                self.expect(Token::In)?;
+                let iter = Box::new(self.condition()?);
+                let body = self.block()?;
+                ExpressionKind::For { var, iter, body }
+            }
+            Token::Match => return self.match_expression(),
+            Token::Pipe | Token::Or => return self.closure(),
+            _ => return Err(self.error("expression")),
+        };
+        Ok(self.new_expression(kind))
+    }
+
+    fn literal(&mut self) -> ParseResult<Literal> {
+        let literal = match self.peek().clone() {
+            Token::Int { text, suffix, .. } => Literal::Integer(text, suffix),
+            Token::Float { text, suffix, .. } => Literal::Float(text, suffix),
+            Token::Bool(value) => Literal::Bool(value),
+            Token::Char(ch) => Literal::Char(ch),
+            Token::String(text) => Literal::String(vec![StringContent::Text(text)]),
+            Token::InterpolatedString(parts) => Literal::String(self.string_contents(parts)?),
+            _ => return Err(self.error("literal")),
+        };
+        self.advance();
+        Ok(literal)
+    }
+
+    fn string_contents(&mut self, parts: Vec<StringPart>) -> ParseResult<Vec<StringContent>> {
+        let mut contents = vec![];
+        for part in parts {
+            contents.push(match part {
+                StringPart::Text(text) => StringContent::Text(text),
+                StringPart::Interpolation(tokens) => {
+                    let mut parser = Parser::new(tokens);
+                    parser.next_id = self.next_id;
+                    parser.edition = self.edition;
+                    (parser.limits, parser.depth) = (self.limits, self.depth);
+                    let expr = parser.expression();
+                    self.next_id = parser.next_id;
+                    // Blocks in the interpolation may have recovered from errors
+                    self.errors.append(&mut parser.errors);
+                    let expr = expr?;
+                    if !parser.at(&Token::Eof) {
+                        return Err(parser.error("`}`"));
+                    }
+                    StringContent::Interpolated(Box::new(expr))
+                }
+            });
+        }
+        Ok(contents)
+    }
+
+    // An identifier, possibly starting a struct or enum literal
+    fn path_expression(&mut self) -> ParseResult<Expression> {
+        let name = self.identifier()?;
+        if self.eat(&Token::DoubleColon) {
+            let variant = self.identifier()?;
+            let payload = if self.eat(&Token::LParen) {
+                let value = self.delimited()?;
+                self.expect(Token::RParen)?;
+                Some(EnumPayload::Tuple(Box::new(value)))
+            } else if !self.no_struct_literal && self.eat(&Token::LBrace) {
+                let fields = self.comma_list(Token::RBrace, Self::field_init)?;
+                Some(EnumPayload::Struct(fields))
+            } else {
+                None
+            };
+            return Ok(self.new_expression(ExpressionKind::EnumLiteral {
+                enum_name: name,
+                variant,
+                payload,
+            }));
+        }
+        let kind = if !self.no_struct_literal && self.eat(&Token::LBrace) {
+            let (fields, base) = self.struct_literal_fields()?;
+            ExpressionKind::StructLiteral { name, fields, base }
+        } else {
+            ExpressionKind::Identifier(name)
+        };
+        Ok(self.new_expression(kind))
+    }
+
+    // Everything after the opening `{`, along with the `..base` that may end the fields
+    fn struct_literal_fields(&mut self) -> ParseResult<(Vec<FieldInit>, Option<Box<Expression>>)> {
+        let mut fields = vec![];
+        if self.eat(&Token::RBrace) {
+            return Ok((fields, None));
+        }
+        loop {
+            if self.eat(&Token::RangeExclusive) {
+                let base = Box::new(self.delimited()?);
+                self.expect(Token::RBrace)?;
+                return Ok((fields, Some(base)));
+            }
+            fields.push(self.field_init()?);
+            if !self.eat(&Token::Comma) {
+                self.expect(Token::RBrace)?;
+                return Ok((fields, None));
+            }
+        }
+    }
+
+    // `name: value`, or just `name` to take the value of the variable of the same name
+    fn field_init(&mut self) -> ParseResult<FieldInit> {
+        let name = self.identifier()?;
+        let value = if self.eat(&Token::Colon) {
+            self.delimited()?
+        } else {
+            self.new_expression(ExpressionKind::Identifier(name.clone()))
+        };
+        Ok(FieldInit {
+            id: self.node_id(),
+            name,
+            value,
+        })
+    }
+
+    fn if_expression(&mut self) -> ParseResult<Expression> {
+        self.expect(Token::If)?;
+        let condition = Box::new(self.condition()?);
+        let then_block = self.block()?;
+        let else_clause = if !self.eat(&Token::Else) {
+            None
+        } else if self.at(&Token::If) {
+            Some(ElseClause::If(Box::new(self.nested(Self::if_expression)?)))
+        } else {
+            Some(ElseClause::Block(self.block()?))
+        };
+        Ok(self.new_expression(ExpressionKind::If {
+            condition,
+            then_block,
+            else_clause,
+        }))
+    }
+
+    fn match_expression(&mut self) -> ParseResult<Expression> {
+        self.expect(Token::Match)?;
+        let scrutinee = Box::new(self.condition()?);
+        self.expect(Token::LBrace)?;
+        let mut arms = vec![];
+        while !self.eat(&Token::RBrace) {
+            let pattern = self.pattern()?;
+            let guard = if self.eat(&Token::If) {
+                Some(self.delimited()?)
+            } else {
+                None
+            };
+            self.expect(Token::Arrow)?;
+            let body = self.delimited()?;
+            self.expect(Token::Comma)?;
+            arms.push(MatchArm {
+                id: self.node_id(),
+                pattern,
+                guard,
+                body,
+            });
+        }
+        Ok(self.new_expression(ExpressionKind::Match { scrutinee, arms }))
+    }
+
+    fn closure(&mut self) -> ParseResult<Expression> {
+        // `||` is lexed as a single token
+        let params = if self.eat(&Token::Or) {
+            vec![]
+        } else {
+            self.expect(Token::Pipe)?;
+            self.comma_list(Token::Pipe, |parser| {
+                let name = parser.identifier()?;
+                let ty = if parser.eat(&Token::Colon) {
+                    Some(parser.ty()?)
+                } else {
+                    None
+                };
+                Ok(ClosureParam {
+                    id: parser.node_id(),
+                    name,
+                    ty,
+                })
+            })?
+        };
+        let (return_type, body) = if self.eat(&Token::Arrow) {
+            let return_type = Some(self.ty()?);
+            let body = ExpressionKind::Block(self.block()?);
+            (return_type, self.new_expression(body))
+        } else {
+            (None, self.expression()?)
+        };
+        Ok(self.new_expression(ExpressionKind::Closure {
+            params,
+            return_type,
+            body: Box::new(body),
+        }))
+    }
+
+    fn pattern(&mut self) -> ParseResult<Pattern> {
+        self.nested(|parser| {
+            let first = parser.single_pattern()?;
+            if !parser.at(&Token::Pipe) {
+                return Ok(first);
+            }
+            let mut alternatives = vec![first];
+            while parser.eat(&Token::Pipe) {
+                alternatives.push(parser.single_pattern()?);
+            }
+            Ok(parser.new_pattern(PatternKind::Or(alternatives)))
+        })
+    }
+
+    // A pattern without `|` at the top
+    fn single_pattern(&mut self) -> ParseResult<Pattern> {
+        let kind = match self.peek() {
+            Token::Identifier(name) if name == "_" => {
+                self.advance();
+                PatternKind::Wildcard
+            }
+            Token::Identifier(_) => self.enum_pattern()?,
+            Token::LParen => {
+                self.advance();
+                match self.parenthesized(Self::pattern)? {
+                    Parenthesized::Tuple(patterns) => PatternKind::Tuple(patterns),
+                    Parenthesized::Single(pattern) => return Ok(pattern),
+                }
+            }
+            Token::LBracket => {
+                self.advance();
+                self.list_pattern()?
+            }
+            Token::Minus
+            | Token::Int { .. }
+            | Token::Float { .. }
+            | Token::String(_)
+            | Token::InterpolatedString(_)
+            | Token::Char(_)
+            | Token::Bool(_) => {
+                let start = self.pattern_literal()?;
+                let inclusive = match self.peek() {
+                    Token::RangeInclusive => true,
+                    Token::RangeExclusive => false,
+                    _ => return Ok(self.new_pattern(PatternKind::Literal(start))),
+                };
+                self.advance();
+                let end = self.pattern_literal()?;
+                PatternKind::Range {
+                    start,
+                    end,
+                    inclusive,
+                }
+            }
+            _ => return Err(self.error("pattern")),
+        };
+        Ok(self.new_pattern(kind))
+    }
+
+    // Everything after the opening `[`. Editions with rest patterns allow one `..` item.
+    fn list_pattern(&mut self) -> ParseResult<PatternKind> {
+        let mut has_rest = false;
+        let patterns = self.comma_list(Token::RBracket, |parser| {
+            if !parser.supports(Feature::ListRest) || !parser.at(&Token::RangeExclusive) {
+                return parser.pattern();
+            }
+            if has_rest {
+                return Err(parser.error("pattern"));
+            }
+            parser.advance();
+            has_rest = true;
+            Ok(parser.new_pattern(PatternKind::Rest))
+        })?;
+        Ok(PatternKind::List(patterns))
+    }
+
+    fn pattern_literal(&mut self) -> ParseResult<Literal> {
+        if !self.eat(&Token::Minus) {
+            return self.literal();
+        }
+        // The sign is kept in the text, as there is no expression to negate
+        match self.peek() {
+            Token::Int { .. } | Token::Float { .. } => {}
+            _ => return Err(self.error("number")),
+        }
+        Ok(match self.literal()? {
+            Literal::Integer(text, suffix) => Literal::Integer(format!("-{text}"), suffix),
+            Literal::Float(text, suffix) => Literal::Float(format!("-{text}"), suffix),
+            _ => unreachable!("checked to be a number above"),
+        })
+    }
+
+    // A plain identifier binds the value, one followed by `{` is a struct and anything
+    // else with a path or payload is a variant
+    fn enum_pattern(&mut self) -> ParseResult<PatternKind> {
+        let first = self.identifier()?;
+        match self.peek() {
+            Token::DoubleColon | Token::LParen => {}
+            Token::LBrace => {
+                self.advance();
+                let (fields, rest) = self.struct_pattern_fields()?;
+                return Ok(PatternKind::Struct {
+                    name: first,
+                    fields,
+                    rest,
+                });
+            }
+            _ => return Ok(PatternKind::Identifier(first)),
+        }
+        let mut segments = vec![first];
+        while self.eat(&Token::DoubleColon) {
+            segments.push(self.identifier()?);
+        }
+        let payload = if self.eat(&Token::LParen) {
+            let pattern = self.pattern()?;
+            self.expect(Token::RParen)?;
+            Some(EnumPatternPayload::Tuple(Box::new(pattern)))
+        } else if self.eat(&Token::LBrace) {
+            let fields = self.comma_list(Token::RBrace, Self::pattern_field)?;
+            Some(EnumPatternPayload::Struct(fields))
+        } else {
+            None
+        };
+        Ok(PatternKind::Enum {
+            path: Path { segments },
+            payload,
+        })
+    }
+
+    // Everything after the opening `{`, along with whether the fields end in `..`
+    fn struct_pattern_fields(&mut self) -> ParseResult<(Vec<PatternField>, bool)> {
+        let mut fields = vec![];
+        if self.eat(&Token::RBrace) {
+            return Ok((fields, false));
+        }
+        loop {
+            if self.eat(&Token::RangeExclusive) {
+                self.expect(Token::RBrace)?;
+                return Ok((fields, true));
+            }
+            fields.push(self.pattern_field()?);
+            if !self.eat(&Token::Comma) {
+                self.expect(Token::RBrace)?;
+                return Ok((fields, false));
+            }
+        }
+    }
+
+    // `name: pattern`, or just `name` to bind the field to a variable of the same name
+    fn pattern_field(&mut self) -> ParseResult<PatternField> {
+        let name = self.identifier()?;
+        let pattern = if self.eat(&Token::Colon) {
+            self.pattern()?
+        } else {
+            self.new_pattern(PatternKind::Identifier(name.clone()))
+        };
+        Ok(PatternField {
+            id: self.node_id(),
+            name,
+            pattern,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::{
+            ParseOptions,
+            tests::{assert_same_tree, e, pat, ty},
+        },
+        token::Span,
+        version::LanguageEdition,
+    };
+
+    fn expr(source: &str) -> Expression {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        let expr = parser
+            .expression()
+            .unwrap_or_else(|e| panic!("{source:?}: {e}"));
+        assert_eq!(parser.peek(), &Token::Eof, "{source:?} not fully parsed");
+        expr
+    }
+
+    fn pattern(source: &str) -> Pattern {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        parser
+            .pattern()
+            .unwrap_or_else(|e| panic!("{source:?}: {e}"))
+    }
+
+    fn ident(name: &str) -> Expression {
+        e(ExpressionKind::Identifier(name.into()))
+    }
+
+    fn int(text: &str) -> Literal {
+        Literal::Integer(text.into(), None)
+    }
+
+    fn block(final_expr: Expression) -> Block {
+        Block {
+            id: NodeId::DUMMY,
+            statements: vec![],
+            final_expr: Some(Box::new(final_expr)),
+        }
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_same_tree(
+            &expr("7u8"),
+            &e(ExpressionKind::Literal(Literal::Integer(
+                "7".into(),
+                Some(crate::token::NumberSuffix::U8),
+            ))),
+        );
+        assert_same_tree(
+            &expr("1.5"),
+            &e(ExpressionKind::Literal(Literal::Float("1.5".into(), None))),
+        );
+        assert_same_tree(
+            &expr("true"),
+            &e(ExpressionKind::Literal(Literal::Bool(true))),
+        );
+        assert_same_tree(
+            &expr("'a'"),
+            &e(ExpressionKind::Literal(Literal::Char('a'))),
+        );
+        assert_same_tree(
+            &expr("\"Hey, #{name.first}!\""),
+            &e(ExpressionKind::Literal(Literal::String(vec![
+                StringContent::Text("Hey, ".into()),
+                StringContent::Interpolated(Box::new(e(ExpressionKind::FieldAccess {
+                    expr: Box::new(ident("name")),
+                    field: "first".into(),
+                }))),
+                StringContent::Text("!".into()),
+            ]))),
+        );
+    }
+
+    #[test]
+    fn test_unary() {
+        assert_same_tree(
+            &expr("-!x"),
+            &e(ExpressionKind::Unary {
+                op: UnaryOperator::Neg,
+                expr: Box::new(e(ExpressionKind::Unary {
+                    op: UnaryOperator::Not,
+                    expr: Box::new(ident("x")),
+                })),
+            }),
+        );
+        // Postfix operators bind tighter
+        assert_same_tree(
+            &expr("~x?"),
+            &e(ExpressionKind::Unary {
+                op: UnaryOperator::BitNot,
+                expr: Box::new(e(ExpressionKind::Try(Box::new(ident("x"))))),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_postfix_chain() {
+        assert_same_tree(
+            &expr("a.b?.c.d(1)?"),
+            &e(ExpressionKind::Try(Box::new(e(
+                ExpressionKind::MethodCall {
+                    receiver: Box::new(e(ExpressionKind::OptionalFieldAccess {
+                        expr: Box::new(e(ExpressionKind::FieldAccess {
+                            expr: Box::new(ident("a")),
+                            field: "b".into(),
+                        })),
+                        field: "c".into(),
+                    })),
+                    method: "d".into(),
+                    args: vec![e(ExpressionKind::Literal(int("1")))],
+                },
+            )))),
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_same_tree(
+            &expr("f(x, self)()"),
+            &e(ExpressionKind::Call {
+                callee: Box::new(e(ExpressionKind::Call {
+                    callee: Box::new(ident("f")),
+                    args: vec![ident("x"), e(ExpressionKind::SelfRef)],
+                })),
+                args: vec![],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_struct_and_enum_literals() {
+        assert_same_tree(
+            &expr("Point { x: 1, y }"),
+            &e(ExpressionKind::StructLiteral {
+                name: "Point".into(),
+                fields: vec![
+                    FieldInit {
+                        id: NodeId::DUMMY,
+                        name: "x".into(),
+                        value: e(ExpressionKind::Literal(int("1"))),
+                    },
+                    FieldInit {
+                        id: NodeId::DUMMY,
+                        name: "y".into(),
+                        value: ident("y"),
+                    },
+                ],
+                base: None,
+            }),
+        );
+        assert_same_tree(
+            &expr("Maybe::Some(10)"),
+            &e(ExpressionKind::EnumLiteral {
+                enum_name: "Maybe".into(),
+                variant: "Some".into(),
+                payload: Some(EnumPayload::Tuple(Box::new(e(ExpressionKind::Literal(
+                    int("10"),
+                ))))),
+            }),
+        );
+        assert_same_tree(
+            &expr("Shape::Rect { w: 1 }"),
+            &e(ExpressionKind::EnumLiteral {
+                enum_name: "Shape".into(),
+                variant: "Rect".into(),
+                payload: Some(EnumPayload::Struct(vec![FieldInit {
+                    id: NodeId::DUMMY,
+                    name: "w".into(),
+                    value: e(ExpressionKind::Literal(int("1"))),
+                }])),
+            }),
+        );
+        assert_same_tree(
+            &expr("Ordering::Less"),
+            &e(ExpressionKind::EnumLiteral {
+                enum_name: "Ordering".into(),
+                variant: "Less".into(),
+                payload: None,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_struct_update() {
+        assert_same_tree(
+            &expr("Point { x: 1, ..origin() }"),
+            &e(ExpressionKind::StructLiteral {
+                name: "Point".into(),
+                fields: vec![FieldInit {
+                    id: NodeId::DUMMY,
+                    name: "x".into(),
+                    value: e(ExpressionKind::Literal(int("1"))),
+                }],
+                base: Some(Box::new(e(ExpressionKind::Call {
+                    callee: Box::new(ident("origin")),
+                    args: vec![],
+                }))),
+            }),
+        );
+        assert_same_tree(
+            &expr("Point { ..p }"),
+            &e(ExpressionKind::StructLiteral {
+                name: "Point".into(),
+                fields: vec![],
+                base: Some(Box::new(ident("p"))),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_tuples_and_lists() {
+        let elements = || vec![ident("a"), e(ExpressionKind::Literal(int("1")))];
+        assert_same_tree(&expr("(a, 1)"), &e(ExpressionKind::Tuple(elements())));
+        assert_same_tree(&expr("[a, 1]"), &e(ExpressionKind::List(elements())));
+        assert_same_tree(&expr("()"), &e(ExpressionKind::Tuple(vec![])));
+        assert_same_tree(&expr("[]"), &e(ExpressionKind::List(vec![])));
+        assert_same_tree(&expr("(a,)"), &e(ExpressionKind::Tuple(vec![ident("a")])));
+        assert_same_tree(&expr("(a)"), &ident("a"));
+    }
+
+    #[test]
+    fn test_if_else_chain() {
+        assert_same_tree(
+            &expr("if a { b } else if c { d } else { e }"),
+            &e(ExpressionKind::If {
+                condition: Box::new(ident("a")),
+                then_block: block(ident("b")),
+                else_clause: Some(ElseClause::If(Box::new(e(ExpressionKind::If {
+                    condition: Box::new(ident("c")),
+                    then_block: block(ident("d")),
+                    else_clause: Some(ElseClause::Block(block(ident("e")))),
+                })))),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_conditions_exclude_struct_literals() {
+        assert_same_tree(
+            &expr("unless p { Point { x: 1 } }"),
+            &e(ExpressionKind::Unless {
+                condition: Box::new(ident("p")),
+                then_block: block(e(ExpressionKind::StructLiteral {
+                    name: "Point".into(),
+                    fields: vec![FieldInit {
+                        id: NodeId::DUMMY,
+                        name: "x".into(),
+                        value: e(ExpressionKind::Literal(int("1"))),
+                    }],
+                    base: None,
+                })),
+                else_block: None,
+            }),
+        );
+        // Unless they're delimited
+        assert!(matches!(
+            expr("while f(P {}) {}").kind,
+            ExpressionKind::While { condition, .. } if matches!(condition.kind, ExpressionKind::Call { .. })
+        ));
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_same_tree(
+            &expr("for x in xs { x }"),
+            &e(ExpressionKind::For {
+                var: "x".into(),
+                iter: Box::new(ident("xs")),
+                body: block(ident("x")),
+            }),
+        );
+        assert_same_tree(
+            &expr("loop { x }"),
+            &e(ExpressionKind::Loop(block(ident("x")))),
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert_same_tree(
+            &expr(
+                "match m {
+                    # Comments between arms are skipped
+                    Some(x) if x -> x,
+                    _ -> { 0 },
+                }",
+            ),
+            &e(ExpressionKind::Match {
+                scrutinee: Box::new(ident("m")),
+                arms: vec![
+                    MatchArm {
+                        id: NodeId::DUMMY,
+                        pattern: pat(PatternKind::Enum {
+                            path: Path {
+                                segments: vec!["Some".into()],
+                            },
+                            payload: Some(EnumPatternPayload::Tuple(Box::new(pat(
+                                PatternKind::Identifier("x".into()),
+                            )))),
+                        }),
+                        guard: Some(ident("x")),
+                        body: ident("x"),
+                    },
+                    MatchArm {
+                        id: NodeId::DUMMY,
+                        pattern: pat(PatternKind::Wildcard),
+                        guard: None,
+                        body: e(ExpressionKind::Block(block(e(ExpressionKind::Literal(
+                            int("0"),
+                        ))))),
+                    },
+                ],
+            }),
+        );
+        let ExpressionKind::Match { arms, .. } = expr("match x { 1 | 2 -> a, }").kind else {
+            panic!("expected a match");
+        };
+        assert!(
+            matches!(&arms[0].pattern.kind, PatternKind::Or(alternatives) if alternatives.len() == 2)
+        );
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert_same_tree(
+            &pattern("(Ordering::Less, 0..=9, -10..0, 'a', n)"),
+            &pat(PatternKind::Tuple(vec![
+                pat(PatternKind::Enum {
+                    path: Path {
+                        segments: vec!["Ordering".into(), "Less".into()],
+                    },
+                    payload: None,
+                }),
+                pat(PatternKind::Range {
+                    start: int("0"),
+                    end: int("9"),
+                    inclusive: true,
+                }),
+                pat(PatternKind::Range {
+                    start: int("-10"),
+                    end: int("0"),
+                    inclusive: false,
+                }),
+                pat(PatternKind::Literal(Literal::Char('a'))),
+                pat(PatternKind::Identifier("n".into())),
+            ])),
+        );
+        assert_same_tree(
+            &pattern("[(x,), _]"),
+            &pat(PatternKind::List(vec![
+                pat(PatternKind::Tuple(vec![pat(PatternKind::Identifier(
+                    "x".into(),
+                ))])),
+                pat(PatternKind::Wildcard),
+            ])),
+        );
+        assert_same_tree(&pattern("(_)"), &pat(PatternKind::Wildcard));
+        assert_same_tree(
+            &pattern("1 | 'a'..='z' | (x | _, 2)"),
+            &pat(PatternKind::Or(vec![
+                pat(PatternKind::Literal(int("1"))),
+                pat(PatternKind::Range {
+                    start: Literal::Char('a'),
+                    end: Literal::Char('z'),
+                    inclusive: true,
+                }),
+                pat(PatternKind::Tuple(vec![
+                    pat(PatternKind::Or(vec![
+                        pat(PatternKind::Identifier("x".into())),
+                        pat(PatternKind::Wildcard),
+                    ])),
+                    pat(PatternKind::Literal(int("2"))),
+                ])),
+            ])),
+        );
+        assert_same_tree(
+            &pattern("Action::Move { x: 0, y }"),
+            &pat(PatternKind::Enum {
+                path: Path {
+                    segments: vec!["Action".into(), "Move".into()],
+                },
+                payload: Some(EnumPatternPayload::Struct(vec![
+                    PatternField {
+                        id: NodeId::DUMMY,
+                        name: "x".into(),
+                        pattern: pat(PatternKind::Literal(int("0"))),
+                    },
+                    PatternField {
+                        id: NodeId::DUMMY,
+                        name: "y".into(),
+                        pattern: pat(PatternKind::Identifier("y".into())),
+                    },
+                ])),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_struct_patterns() {
+        assert_same_tree(
+            &pattern("Point { x, y: _, .. }"),
+            &pat(PatternKind::Struct {
+                name: "Point".into(),
+                fields: vec![
+                    PatternField {
+                        id: NodeId::DUMMY,
+                        name: "x".into(),
+                        pattern: pat(PatternKind::Identifier("x".into())),
+                    },
+                    PatternField {
+                        id: NodeId::DUMMY,
+                        name: "y".into(),
+                        pattern: pat(PatternKind::Wildcard),
+                    },
+                ],
+                rest: true,
+            }),
+        );
+        assert!(matches!(
+            pattern("Unit {}").kind,
+            PatternKind::Struct { fields, rest: false, .. } if fields.is_empty()
+        ));
+        assert!(matches!(
+            pattern("Point { .. }").kind,
+            PatternKind::Struct { fields, rest: true, .. } if fields.is_empty()
+        ));
+        assert!(matches!(
+            expr("match p { Point { x, .. } -> x, }").kind,
+            ExpressionKind::Match { arms, .. }
+                if matches!(arms[0].pattern.kind, PatternKind::Struct { rest: true, .. })
+        ));
+    }
+
+    #[test]
+    fn test_list_rest_patterns() {
+        let experimental = |source: &str| {
+            let options = ParseOptions {
+                edition: LanguageEdition::Experimental,
+                ..ParseOptions::default()
+            };
+            Parser::with_options(Lexer::new(source).collect(), &options).pattern()
+        };
+        let name = |name: &str| pat(PatternKind::Identifier(name.into()));
+        assert_same_tree(
+            &experimental("[first, .., last]").unwrap(),
+            &pat(PatternKind::List(vec![
+                name("first"),
+                pat(PatternKind::Rest),
+                name("last"),
+            ])),
+        );
+        assert_same_tree(
+            &experimental("[..]").unwrap(),
+            &pat(PatternKind::List(vec![pat(PatternKind::Rest)])),
+        );
+        assert_eq!(
+            experimental("[a, .., ..]").unwrap_err().to_string(),
+            "expected pattern, found `..`"
+        );
+        // The default edition doesn't have the syntax at all
+        let mut parser = Parser::new(Lexer::new("[first, ..]").collect());
+        assert_eq!(
+            parser.pattern().unwrap_err().to_string(),
+            "expected pattern, found `..`"
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        assert_same_tree(
+            &expr("|x, y: int| x"),
+            &e(ExpressionKind::Closure {
+                params: vec![
+                    ClosureParam {
+                        id: NodeId::DUMMY,
+                        name: "x".into(),
+                        ty: None,
+                    },
+                    ClosureParam {
+                        id: NodeId::DUMMY,
+                        name: "y".into(),
+                        ty: Some(ty(TypeKind::Int)),
+                    },
+                ],
+                return_type: None,
+                body: Box::new(ident("x")),
+            }),
+        );
+        assert_same_tree(
+            &expr("|| -> int { 1 }"),
+            &e(ExpressionKind::Closure {
+                params: vec![],
+                return_type: Some(ty(TypeKind::Int)),
+                body: Box::new(e(ExpressionKind::Block(block(e(ExpressionKind::Literal(
+                    int("1"),
+                )))))),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        let error = |source: &str| {
+            let mut parser = Parser::new(Lexer::new(source).collect());
+            parser.expression().unwrap_err().to_string()
+        };
+        assert_eq!(error(";"), "expected expression, found `;`");
+        assert_eq!(error("\"#{a b}\""), "expected `}`, found identifier `b`");
+        assert_eq!(error("f(a b)"), "expected `)`, found identifier `b`");
+        assert_eq!(error("a.0x1"), "expected tuple index, found number `0x1`");
+        assert_eq!(error("a.1e3"), "expected tuple index, found number `1e3`");
+        assert_eq!(error("a.01"), "expected tuple index, found number `01`");
+        assert_eq!(error("a.0_1"), "expected tuple index, found number `0_1`");
+        assert_eq!(error("a.0.01"), "expected tuple index, found number `0.01`");
+        assert_eq!(
+            error("a.1_0.2"),
+            "expected tuple index, found number `1_0.2`"
+        );
+        assert_eq!(error("P { ..p, x: 1 }"), "expected `}`, found `,`");
+        assert_eq!(
+            error("match x { - a -> 1, }"),
+            "expected number, found identifier `a`"
+        );
+
+        // An empty interpolation is reported where it ends, not at the start of the input
+        let mut parser = Parser::new(Lexer::new("x + \"a #{}\"").collect());
+        let error = parser.expression().unwrap_err();
+        assert_eq!(error.expected, "expression");
+        assert_eq!(error.span, Span { start: 9, end: 9 });
+    }
+}
@@ -0,0 +1,301 @@
+// Operator precedence for the Pratt loop in `expr.rs`, loosest first. Postfix operators
+// (calls, indexing, `.`, `?.`, `?`) bind tighter than all of these and are parsed separately.
+
+use crate::{
+    ast::{BinaryOperator, Expression, ExpressionKind},
+    token::Token,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Precedence {
+    Range,          // .. ..=
+    Coalesce,       // ??
+    Or,             // ||
+    And,            // &&
+    Comparison,     // == != < > <= >=
+    BitOr,          // |
+    BitXor,         // ^
+    BitAnd,         // &
+    Shift,          // << >>
+    Additive,       // + -
+    Multiplicative, // * / %
+    Prefix,         // - ! ~
+    Power,          // **, so `-x ** 2` is `-(x ** 2)`
+}
+
+impl Precedence {
+    // Minimum binding power an operator needs to be part of an operand at this level
+    pub(super) fn power(self) -> u8 {
+        self as u8 * 2
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Infix {
+    Binary(BinaryOperator),
+    Coalesce,
+    Range { inclusive: bool },
+}
+
+impl Infix {
+    pub(super) fn from_token(token: &Token) -> Option<Self> {
+        use BinaryOperator::*;
+        let op = match token {
+            Token::RangeExclusive => return Some(Self::Range { inclusive: false }),
+            Token::RangeInclusive => return Some(Self::Range { inclusive: true }),
+            Token::DoubleQuestion => return Some(Self::Coalesce),
+            Token::Plus => Add,
+            Token::Minus => Sub,
+            Token::Star => Mul,
+            Token::Slash => Div,
+            Token::Percent => Mod,
+            Token::StarStar => Pow,
+            Token::EqEq => Eq,
+            Token::NotEq => NotEq,
+            Token::Lt => Lt,
+            Token::Gt => Gt,
+            Token::Le => Le,
+            Token::Ge => Ge,
+            Token::Or => Or,
+            Token::And => And,
+            Token::Amp => BitAnd,
+            Token::Pipe => BitOr,
+            Token::Caret => BitXor,
+            Token::LShift => Shl,
+            Token::RShift => Shr,
+            _ => return None,
+        };
+        Some(Self::Binary(op))
+    }
+
+    pub(super) fn precedence(self) -> Precedence {
+        use BinaryOperator::*;
+        match self {
+            Self::Range { .. } => Precedence::Range,
+            Self::Coalesce => Precedence::Coalesce,
+            Self::Binary(op) => match op {
+                Or => Precedence::Or,
+                And => Precedence::And,
+                Eq | NotEq | Lt | Gt | Le | Ge => Precedence::Comparison,
+                BitOr => Precedence::BitOr,
+                BitXor => Precedence::BitXor,
+                BitAnd => Precedence::BitAnd,
+                Shl | Shr => Precedence::Shift,
+                Add | Sub => Precedence::Additive,
+                Mul | Div | Mod => Precedence::Multiplicative,
+                Pow => Precedence::Power,
+            },
+        }
+    }
+
+    pub(super) fn apply(self, left: Expression, right: Expression) -> ExpressionKind {
+        let (left, right) = (Box::new(left), Box::new(right));
+        match self {
+            Self::Binary(op) => ExpressionKind::Binary { left, op, right },
+            Self::Coalesce => ExpressionKind::Coalesce {
+                value: left,
+                default: right,
+            },
+            Self::Range { inclusive } => ExpressionKind::Range {
+                start: left,
+                end: right,
+                inclusive,
+            },
+        }
+    }
+
+    fn is_right_associative(self) -> bool {
+        matches!(self, Self::Coalesce | Self::Binary(BinaryOperator::Pow))
+    }
+
+    // Left and right binding powers. The right one is the minimum for the right operand,
+    // so a higher right power makes the operator left-associative.
+    pub(super) fn binding_power(self) -> (u8, u8) {
+        let power = self.precedence().power() + 1;
+        if self.is_right_associative() {
+            (power, power)
+        } else {
+            (power, power + 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::*;
+    use crate::{
+        ast::{Expression, ExpressionKind, Literal, UnaryOperator},
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    const OPERATORS: [&str; 22] = [
+        "..", "..=", "??", "||", "&&", "==", "!=", "<", ">", "<=", ">=", "|", "^", "&", "<<", ">>",
+        "+", "-", "*", "/", "%", "**",
+    ];
+
+    // Renders the tree as an s-expression, e.g. `(+ a (* b c))`
+    fn sexp(expr: &Expression) -> String {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::Literal(Literal::Integer(text, _)) => text.clone(),
+            ExpressionKind::Binary { left, op, right } => {
+                let token = OPERATORS
+                    .iter()
+                    .find(|text| {
+                        let token = &Lexer::new(text).next().unwrap().value;
+                        Infix::from_token(token) == Some(Infix::Binary(*op))
+                    })
+                    .unwrap();
+                format!("({token} {} {})", sexp(left), sexp(right))
+            }
+            ExpressionKind::Coalesce { value, default } => {
+                format!("(?? {} {})", sexp(value), sexp(default))
+            }
+            ExpressionKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let token = if *inclusive { "..=" } else { ".." };
+                format!("({token} {} {})", sexp(start), sexp(end))
+            }
+            ExpressionKind::Unary { op, expr } => {
+                let token = match op {
+                    UnaryOperator::Neg => "-",
+                    UnaryOperator::Not => "!",
+                    UnaryOperator::BitNot => "~",
+                };
+                format!("({token} {})", sexp(expr))
+            }
+            ExpressionKind::Call { callee, args } => {
+                let args: Vec<_> = args.iter().map(sexp).collect();
+                format!("(call {} {})", sexp(callee), args.join(" "))
+            }
+            ExpressionKind::FieldAccess { expr, field } => format!("(. {} {field})", sexp(expr)),
+            ExpressionKind::MethodCall {
+                receiver,
+                method,
+                args,
+            } => {
+                let args: Vec<_> = args.iter().map(sexp).collect();
+                format!("(.{method} {} {})", sexp(receiver), args.join(" "))
+            }
+            ExpressionKind::Try(expr) => format!("(? {})", sexp(expr)),
+            ExpressionKind::TupleIndex { expr, index } => format!("(. {} {index})", sexp(expr)),
+            ExpressionKind::Index { expr, index } => {
+                format!("([] {} {})", sexp(expr), sexp(index))
+            }
+            other => panic!("unexpected expression {other:?}"),
+        }
+    }
+
+    fn parse(source: &str) -> Result<String, String> {
+        let mut parser = Parser::new(Lexer::new(source).collect());
+        let expr = parser.expression().map_err(|e| e.to_string())?;
+        if !parser.at(&Token::Eof) {
+            return Err(parser.error("end of expression").to_string());
+        }
+        Ok(sexp(&expr))
+    }
+
+    fn infix(text: &str) -> Infix {
+        Infix::from_token(&Lexer::new(text).next().unwrap().value).unwrap()
+    }
+
+    #[test]
+    fn test_every_operator_pair() {
+        for first in OPERATORS {
+            for second in OPERATORS {
+                let source = format!("a {first} b {second} c");
+                let (a, b) = (infix(first), infix(second));
+
+                if matches!((a, b), (Infix::Range { .. }, Infix::Range { .. })) {
+                    // Ranges don't chain
+                    assert!(parse(&source).is_err(), "{source}");
+                    continue;
+                }
+                let binds_left = match a.precedence().cmp(&b.precedence()) {
+                    Ordering::Less => false,
+                    Ordering::Equal => !a.is_right_associative(),
+                    Ordering::Greater => true,
+                };
+                let expected = if binds_left {
+                    format!("({second} ({first} a b) c)")
+                } else {
+                    format!("({first} a ({second} b c))")
+                };
+                assert_eq!(parse(&source), Ok(expected), "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_precedence_order() {
+        let levels: Vec<_> = OPERATORS.iter().map(|op| infix(op).precedence()).collect();
+        assert!(
+            levels.is_sorted(),
+            "OPERATORS should go from loosest to tightest"
+        );
+        assert_eq!(
+            parse("a || b && c == d | e ^ f & g << h + i * j ** k").unwrap(),
+            "(|| a (&& b (== c (| d (^ e (& f (<< g (+ h (* i (** j k))))))))))"
+        );
+    }
+
+    #[test]
+    fn test_prefix_operators() {
+        assert_eq!(parse("-a * b"), Ok("(* (- a) b)".into()));
+        assert_eq!(parse("-a ** b"), Ok("(- (** a b))".into()));
+        assert_eq!(parse("a ** -b ** c"), Ok("(** a (- (** b c)))".into()));
+        assert_eq!(parse("!a && ~b"), Ok("(&& (! a) (~ b))".into()));
+        assert_eq!(parse("a - -b"), Ok("(- a (- b))".into()));
+    }
+
+    #[test]
+    fn test_postfix_operators() {
+        assert_eq!(parse("-a.b(c)?"), Ok("(- (? (.b a c)))".into()));
+        assert_eq!(
+            parse("f(a + b) * c.d"),
+            Ok("(* (call f (+ a b)) (. c d))".into())
+        );
+        assert_eq!(parse("a.0.1.2"), Ok("(. (. (. a 0) 1) 2)".into()));
+        assert_eq!(parse("a.0.b(c).1"), Ok("(. (.b (. a 0) c) 1)".into()));
+        assert_eq!(parse("-a[i + 1]"), Ok("(- ([] a (+ i 1)))".into()));
+        assert_eq!(
+            parse("a.b[0](c)[d]"),
+            Ok("([] (call ([] (. a b) 0) c) d)".into())
+        );
+        assert_eq!(
+            parse("a.b().c(1 + 2).d"),
+            Ok("(. (.c (.b a ) (+ 1 2)) d)".into())
+        );
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(parse("(a + b) * c"), Ok("(* (+ a b) c)".into()));
+        assert_eq!(parse("a ** (b ** c)"), Ok("(** a (** b c))".into()));
+        assert_eq!(parse("(a ** b) ** c"), Ok("(** (** a b) c)".into()));
+        assert_eq!(parse("(a..b)"), Ok("(.. a b)".into()));
+        assert_eq!(
+            parse("(a + b"),
+            Err("expected `)`, found end of file".into())
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(parse("0..n + 1"), Ok("(.. 0 (+ n 1))".into()));
+        assert_eq!(
+            parse("a ?? 0..=b || c"),
+            Ok("(..= (?? a 0) (|| b c))".into())
+        );
+        assert_eq!(
+            parse("a..b..c"),
+            Err("expected end of expression, found `..`".into())
+        );
+    }
+}
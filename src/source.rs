@@ -0,0 +1,240 @@
+use std::fmt;
+
+use crate::token::Span;
+
+/// Source code as the lexer should see it: valid UTF-8 with no byte order mark, and every
+/// `\r\n` turned into `\n`, so spans and columns are the same whatever editor saved it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceText {
+    text: String,
+    line_ending: LineEnding,
+}
+
+/// The line terminator a file used before normalization, so tools writing it back (like
+/// the formatter) can restore it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodingError {
+    pub offset: usize, // Of the first invalid byte, counting from the start of the file
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid UTF-8 at byte {}", self.offset)
+    }
+}
+
+impl SourceText {
+    const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EncodingError> {
+        let (bom, rest) = match bytes.strip_prefix(Self::BOM) {
+            Some(rest) => (Self::BOM.len(), rest),
+            None => (0, bytes),
+        };
+        match std::str::from_utf8(rest) {
+            Ok(text) => Ok(Self::from(text)),
+            Err(error) => Err(EncodingError {
+                offset: bom + error.valid_up_to(),
+            }),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+}
+
+impl From<&str> for SourceText {
+    fn from(text: &str) -> Self {
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+        // Decided by the first line break; lone `\r`s are left alone
+        let line_ending = match text.find('\n') {
+            Some(i) if text[..i].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+        Self {
+            text: text.replace("\r\n", "\n"),
+            line_ending,
+        }
+    }
+}
+
+/// A 1-based line/column pair. Columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Maps byte offsets (as stored in `Span`) back to line/column positions.
+pub struct SourceMap<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Position of the first character of `span`.
+    pub fn position(&self, span: &Span) -> Position {
+        self.offset_position(span.start)
+    }
+
+    /// Start and end positions of `span`. The end is exclusive, like the span itself.
+    pub fn range(&self, span: &Span) -> (Position, Position) {
+        (
+            self.offset_position(span.start),
+            self.offset_position(span.end),
+        )
+    }
+
+    pub fn offset_position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        // Offsets inside a multi-byte character are attributed to that character
+        let column = self.source[line_start..]
+            .char_indices()
+            .take_while(|(i, _)| line_start + i < offset)
+            .count();
+        Position {
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+
+    /// The text of the given 1-based line, without its line terminator.
+    pub fn line_text(&self, line: usize) -> Option<&'a str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |&next| next - 1);
+        Some(self.source[start..end].trim_end_matches('\r'))
+    }
+}
+
+/// Replaces the bytes covered by `span` (offsets into the old text) with `text`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = String::with_capacity(source.len() + self.text.len());
+        edited.push_str(&source[..self.span.start]);
+        edited.push_str(&self.text);
+        edited.push_str(&source[self.span.end..]);
+        edited
+    }
+
+    /// How far offsets after the edit move.
+    pub fn delta(&self) -> isize {
+        self.text.len() as isize - (self.span.end - self.span.start) as isize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn pos(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn test_offset_positions() {
+        let map = SourceMap::new("let a = 1;\nlet b = 2;\n");
+        assert_eq!(map.offset_position(0), pos(1, 1));
+        assert_eq!(map.offset_position(4), pos(1, 5));
+        assert_eq!(map.offset_position(10), pos(1, 11));
+        assert_eq!(map.offset_position(11), pos(2, 1));
+        assert_eq!(map.offset_position(22), pos(3, 1));
+        assert_eq!(map.line_count(), 3);
+    }
+
+    #[test]
+    fn test_columns_count_chars() {
+        let map = SourceMap::new("\"héllo\" x");
+        assert_eq!(map.offset_position(9), pos(1, 9));
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let source = "fn main() {\n    foo\n}";
+        let map = SourceMap::new(source);
+        let tokens: Vec<_> = Lexer::new(source).collect();
+        let foo = &tokens[5];
+        assert_eq!(foo.value, crate::token::Token::Identifier("foo".into()));
+        assert_eq!(map.range(&foo.span), (pos(2, 5), pos(2, 8)));
+        assert_eq!(map.position(&tokens[6].span), pos(3, 1));
+    }
+
+    #[test]
+    fn test_text_edit() {
+        let edit = TextEdit {
+            span: Span { start: 4, end: 5 },
+            text: "abc".into(),
+        };
+        assert_eq!(edit.apply("let a = 1;"), "let abc = 1;");
+        assert_eq!(edit.delta(), 2);
+    }
+
+    #[test]
+    fn test_source_text() {
+        let source = SourceText::from_bytes(b"\xEF\xBB\xBFlet a = 1;\r\nlet b = 2;\r\n").unwrap();
+        assert_eq!(source.as_str(), "let a = 1;\nlet b = 2;\n");
+        assert_eq!(source.line_ending(), LineEnding::CrLf);
+
+        let source = SourceText::from("a\nb\r\nc\rd");
+        assert_eq!(source.as_str(), "a\nb\nc\rd");
+        assert_eq!(source.line_ending(), LineEnding::Lf);
+        assert_eq!(SourceText::from("\u{feff}x").as_str(), "x");
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let error = SourceText::from_bytes(b"let a = \"\xC3\x28\";").unwrap_err();
+        assert_eq!(error, EncodingError { offset: 9 });
+        assert_eq!(error.to_string(), "invalid UTF-8 at byte 9");
+        // Offsets count the byte order mark
+        let error = SourceText::from_bytes(b"\xEF\xBB\xBFa\xFF").unwrap_err();
+        assert_eq!(error.offset, 4);
+        // Truncated characters are invalid too
+        assert_eq!(SourceText::from_bytes(b"ab\xE2\x82").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn test_line_text() {
+        let map = SourceMap::new("first\r\nsecond\nthird");
+        assert_eq!(map.line_text(1), Some("first"));
+        assert_eq!(map.line_text(2), Some("second"));
+        assert_eq!(map.line_text(3), Some("third"));
+        assert_eq!(map.line_text(0), None);
+        assert_eq!(map.line_text(4), None);
+    }
+}
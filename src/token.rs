@@ -1,15 +1,22 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Token {
     Identifier(String),
 
     // Keywords
+    As,       // 'as'
     Break,    // 'break'
     Const,    // 'const'
     Continue, // 'continue'
+    Else,     // 'else'
     Enum,     // 'enum'
     False,    // 'false'
     Fn,       // 'fn'
     For,      // 'for'
+    If,       // 'if'
+    In,       // 'in'
     Let,      // 'let'
     Loop,     // 'loop'
     Match,    // 'match'
@@ -17,17 +24,32 @@ pub enum Token {
     Mut,      // 'mut'
     Proto,    // 'proto'
     Pub,      // 'pub'
+    Return,   // 'return'
+    SelfKw,   // 'self'
     Struct,   // 'struct'
     True,     // 'true'
+    Unless,   // 'unless'
     Use,      // 'use'
     While,    // 'while'
 
     // Primitives
-    Int(i64),       // 'int'
-    Float(f64),     // 'float'
+    // 'int' and 'float'. `text` is the literal as written, minus any suffix. Literals
+    // are never negative: a leading `-` is lexed as `Minus`
+    Int {
+        text: String,
+        value: u64,
+        suffix: Option<NumberSuffix>,
+    },
+    Float {
+        text: String,
+        value: f64,
+        suffix: Option<NumberSuffix>,
+    },
     String(String), // 'str'
     Char(char),     // 'char'
     Bool(bool),     // 'bool'
+    // A string containing `#{...}`, e.g. "Hey, #{name}"
+    InterpolatedString(Vec<StringPart>),
 
     // Operators & Punctuation
     Amp,            // '&'
@@ -39,6 +61,7 @@ pub enum Token {
     Comma,          // ','
     Dot,            // '.'
     DoubleColon,    // '::'
+    DoubleQuestion, // '??'
     Eq,             // '=',
     EqEq,           // '==',
     Ge,             // '>=',
@@ -55,6 +78,8 @@ pub enum Token {
     Percent,        // '%'
     Pipe,           // '|'
     Plus,           // '+'
+    Question,       // '?'
+    QuestionDot,    // '?.'
     RBrace,         // '}
     RBracket,       // ']
     RParen,         // ')
@@ -64,14 +89,228 @@ pub enum Token {
     Semicolon,      // ';
     Slash,          // '/'
     Star,           // '*'
+    StarStar,       // '**'
     Tilde,          // '~',
 
     Comment(String),
-    Unknown(char),
-    UnterminatedString,
-    UnterminatedChar,
-    UnterminatedComment(String),
-    InvalidCharLiteral, // More than one char in char literal
+    Eof, // Emitted once, with an empty span at the end of the source
+}
+
+impl Token {
+    /// The fixed source text of keywords, operators, and punctuation.
+    pub fn symbol(&self) -> Option<&'static str> {
+        Some(match self {
+            Token::As => "as",
+            Token::Break => "break",
+            Token::Const => "const",
+            Token::Continue => "continue",
+            Token::Else => "else",
+            Token::Enum => "enum",
+            Token::False | Token::Bool(false) => "false",
+            Token::Fn => "fn",
+            Token::For => "for",
+            Token::If => "if",
+            Token::In => "in",
+            Token::Let => "let",
+            Token::Loop => "loop",
+            Token::Match => "match",
+            Token::Mod => "mod",
+            Token::Mut => "mut",
+            Token::Proto => "proto",
+            Token::Pub => "pub",
+            Token::Return => "return",
+            Token::SelfKw => "self",
+            Token::Struct => "struct",
+            Token::True | Token::Bool(true) => "true",
+            Token::Unless => "unless",
+            Token::Use => "use",
+            Token::While => "while",
+            Token::Amp => "&",
+            Token::And => "&&",
+            Token::Arrow => "->",
+            Token::Bang => "!",
+            Token::Caret => "^",
+            Token::Colon => ":",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::DoubleColon => "::",
+            Token::DoubleQuestion => "??",
+            Token::Eq => "=",
+            Token::EqEq => "==",
+            Token::Ge => ">=",
+            Token::Gt => ">",
+            Token::LBrace => "{",
+            Token::LBracket => "[",
+            Token::LParen => "(",
+            Token::LShift => "<<",
+            Token::Le => "<=",
+            Token::Lt => "<",
+            Token::Minus => "-",
+            Token::NotEq => "!=",
+            Token::Or => "||",
+            Token::Percent => "%",
+            Token::Pipe => "|",
+            Token::Plus => "+",
+            Token::Question => "?",
+            Token::QuestionDot => "?.",
+            Token::RBrace => "}",
+            Token::RBracket => "]",
+            Token::RParen => ")",
+            Token::RShift => ">>",
+            Token::RangeExclusive => "..",
+            Token::RangeInclusive => "..=",
+            Token::Semicolon => ";",
+            Token::Slash => "/",
+            Token::Star => "*",
+            Token::StarStar => "**",
+            Token::Tilde => "~",
+            Token::Identifier(_)
+            | Token::Int { .. }
+            | Token::Float { .. }
+            | Token::String(_)
+            | Token::Char(_)
+            | Token::InterpolatedString(_)
+            | Token::Comment(_)
+            | Token::Eof => return None,
+        })
+    }
+}
+
+// User-facing description, for messages like "expected `;`, found `}`"
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "identifier `{name}`"),
+            Token::Int { text, suffix, .. } | Token::Float { text, suffix, .. } => {
+                let suffix = suffix.map_or("", |x| x.as_str());
+                write!(f, "number `{text}{suffix}`")
+            }
+            Token::String(_) | Token::InterpolatedString(_) => write!(f, "string literal"),
+            Token::Char(_) => write!(f, "character literal"),
+            Token::Comment(_) => write!(f, "comment"),
+            Token::Eof => write!(f, "end of file"),
+            Token::As
+            | Token::Break
+            | Token::Const
+            | Token::Continue
+            | Token::Else
+            | Token::Enum
+            | Token::False
+            | Token::Bool(_)
+            | Token::Fn
+            | Token::For
+            | Token::If
+            | Token::In
+            | Token::Let
+            | Token::Loop
+            | Token::Match
+            | Token::Mod
+            | Token::Mut
+            | Token::Proto
+            | Token::Pub
+            | Token::Return
+            | Token::SelfKw
+            | Token::Struct
+            | Token::True
+            | Token::Unless
+            | Token::Use
+            | Token::While
+            | Token::Amp
+            | Token::And
+            | Token::Arrow
+            | Token::Bang
+            | Token::Caret
+            | Token::Colon
+            | Token::Comma
+            | Token::Dot
+            | Token::DoubleColon
+            | Token::DoubleQuestion
+            | Token::Eq
+            | Token::EqEq
+            | Token::Ge
+            | Token::Gt
+            | Token::LBrace
+            | Token::LBracket
+            | Token::LParen
+            | Token::LShift
+            | Token::Le
+            | Token::Lt
+            | Token::Minus
+            | Token::NotEq
+            | Token::Or
+            | Token::Percent
+            | Token::Pipe
+            | Token::Plus
+            | Token::Question
+            | Token::QuestionDot
+            | Token::RBrace
+            | Token::RBracket
+            | Token::RParen
+            | Token::RShift
+            | Token::RangeExclusive
+            | Token::RangeInclusive
+            | Token::Semicolon
+            | Token::Slash
+            | Token::Star
+            | Token::StarStar
+            | Token::Tilde => {
+                write!(f, "`{}`", self.symbol().unwrap_or_default())
+            }
+        }
+    }
+}
+
+// Explicit type suffix on a numeric literal, e.g. the `u8` in `7u8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NumberSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumberSuffix {
+    pub fn parse(suffix: &str) -> Option<Self> {
+        Some(match suffix {
+            "i8" => Self::I8,
+            "i16" => Self::I16,
+            "i32" => Self::I32,
+            "i64" => Self::I64,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            "f32" => Self::F32,
+            "f64" => Self::F64,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -85,3 +324,45 @@ pub struct WithSpan<T> {
     pub value: T,
     pub span: Span,
 }
+
+pub type TokenStream = Vec<WithSpan<Token>>;
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum StringPart {
+    Text(String),
+    // Tokens between `#{` and `}`, with absolute spans, ending in an `Eof` at the `}`
+    Interpolation(Vec<WithSpan<Token>>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Token::Semicolon.to_string(), "`;`");
+        assert_eq!(Token::RangeInclusive.to_string(), "`..=`");
+        assert_eq!(Token::SelfKw.to_string(), "`self`");
+        assert_eq!(Token::Bool(true).to_string(), "`true`");
+        assert_eq!(
+            Token::Identifier("foo".into()).to_string(),
+            "identifier `foo`"
+        );
+        assert_eq!(
+            Token::Int {
+                text: "0xFF".into(),
+                value: 255,
+                suffix: Some(NumberSuffix::U8),
+            }
+            .to_string(),
+            "number `0xFFu8`"
+        );
+        assert_eq!(Token::String("hi".into()).to_string(), "string literal");
+        assert_eq!(Token::Eof.to_string(), "end of file");
+        assert_eq!(
+            format!("expected {}, found {}", Token::Semicolon, Token::RBrace),
+            "expected `;`, found `}`"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// What a host embedding the front end can count on: the version of the public API, and the
+// language editions the parser accepts.
+
+use std::{fmt, str::FromStr};
+
+/// Version of the public `token`, `ast` and `parser` API. A minor bump only adds things,
+/// like enum variants (the grammar-facing enums are `#[non_exhaustive]`), and a major bump
+/// changes or removes them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApiVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ApiVersion {
+    pub const CURRENT: Self = Self { major: 0, minor: 1 };
+
+    /// Whether code written against `required` works with this version.
+    pub fn supports(self, required: Self) -> bool {
+        self.major == required.major && self.minor >= required.minor
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// The set of syntax the parser accepts. Editions are ordered, and each one accepts
+/// everything the ones before it do, so code keeps parsing under newer editions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum LanguageEdition {
+    #[default]
+    Edition2025,
+    /// Syntax that may still change or go away. Not meant for code that has to keep
+    /// parsing.
+    Experimental,
+}
+
+impl LanguageEdition {
+    pub const ALL: &[Self] = &[Self::Edition2025, Self::Experimental];
+
+    /// The edition as written in settings, e.g. `edition = "2025"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Edition2025 => "2025",
+            Self::Experimental => "experimental",
+        }
+    }
+
+    pub fn supports(self, feature: Feature) -> bool {
+        self >= feature.edition()
+    }
+
+    pub fn features(self) -> Vec<Feature> {
+        Feature::ALL
+            .iter()
+            .copied()
+            .filter(|&feature| self.supports(feature))
+            .collect()
+    }
+}
+
+impl fmt::Display for LanguageEdition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for LanguageEdition {
+    type Err = UnknownEdition;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|edition| edition.name() == name)
+            .ok_or_else(|| UnknownEdition { name: name.into() })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownEdition {
+    pub name: String,
+}
+
+impl fmt::Display for UnknownEdition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let known: Vec<_> = LanguageEdition::ALL.iter().map(|e| e.name()).collect();
+        write!(
+            f,
+            "unknown edition `{}`, expected one of: {}",
+            self.name,
+            known.join(", ")
+        )
+    }
+}
+
+/// Syntax that not every edition accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Feature {
+    ListRest, // `[first, .., last]`
+}
+
+impl Feature {
+    pub const ALL: &[Self] = &[Self::ListRest];
+
+    /// The first edition that accepts the feature.
+    pub fn edition(self) -> LanguageEdition {
+        match self {
+            Self::ListRest => LanguageEdition::Experimental,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ListRest => "list_rest",
+        }
+    }
+}
+
+/// What this build offers under one edition, for hosts to check before they hand code
+/// to the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub api: ApiVersion,
+    pub edition: LanguageEdition,
+    pub features: Vec<Feature>,
+}
+
+impl Capabilities {
+    pub fn of(edition: LanguageEdition) -> Self {
+        Self {
+            api: ApiVersion::CURRENT,
+            edition,
+            features: edition.features(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_version() {
+        let current = ApiVersion::CURRENT;
+        assert!(current.supports(current));
+        assert!(current.supports(ApiVersion {
+            minor: 0,
+            ..current
+        }));
+        assert!(!current.supports(ApiVersion {
+            minor: current.minor + 1,
+            ..current
+        }));
+        assert!(!current.supports(ApiVersion {
+            major: current.major + 1,
+            minor: 0
+        }));
+        assert_eq!(
+            ApiVersion {
+                major: 1,
+                minor: 12
+            }
+            .to_string(),
+            "1.12"
+        );
+    }
+
+    #[test]
+    fn test_edition_names() {
+        for &edition in LanguageEdition::ALL {
+            assert_eq!(edition.to_string().parse(), Ok(edition));
+        }
+        assert!(LanguageEdition::ALL.is_sorted());
+        assert_eq!("2025".parse(), Ok(LanguageEdition::Edition2025));
+        let error = "2030".parse::<LanguageEdition>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown edition `2030`, expected one of: 2025, experimental"
+        );
+    }
+
+    #[test]
+    fn test_capabilities() {
+        assert_eq!(Capabilities::of(LanguageEdition::default()).features, []);
+        let experimental = Capabilities::of(LanguageEdition::Experimental);
+        assert_eq!(experimental.api, ApiVersion::CURRENT);
+        assert_eq!(experimental.edition, LanguageEdition::Experimental);
+        assert_eq!(experimental.features, [Feature::ListRest]);
+    }
+}